@@ -0,0 +1,170 @@
+use std::{
+    collections::VecDeque,
+    env,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crossbeam_channel::Receiver;
+use error_stack::{AttachmentKind, FrameKind, Report, Result};
+use thiserror::Error;
+
+#[cfg(target_os = "linux")]
+mod pulse;
+pub(crate) mod wav;
+
+/// How many samples are read from a source at a time
+const CHUNK_SIZE: usize = 512;
+
+#[derive(Debug, Error)]
+#[error("Could not listen to the audio")]
+pub struct AudioError;
+
+/// The error followed by the details attached to it on a single line, for places with little room like the GUI toasts
+pub fn summary(report: &Report<AudioError>) -> String {
+    let details = report.frames().filter_map(|frame| match frame.kind() {
+        FrameKind::Attachment(AttachmentKind::Printable(attachment)) => Some(attachment.to_string()),
+        _ => None,
+    });
+
+    std::iter::once(report.current_context().to_string()).chain(details).collect::<Vec<String>>().join(": ")
+}
+
+/// Somewhere to listen to sound from
+pub trait AudioSource {
+    fn sample_rate(&self) -> u32;
+
+    /// Fill `buffer` with the next samples, mixed down to mono and between -1 and 1, blocking until they are available
+    fn read(&mut self, buffer: &mut [f32]) -> Result<(), AudioError>;
+}
+
+/// Open the source `LEGION_KEYBOARD_AUDIO_SOURCE` points to
+///
+/// A path to a `.wav` file plays it back on a loop, anything else is the name of a PulseAudio/PipeWire device to record from.
+/// When unset, what is being played through the default output is used.
+pub fn open_source() -> Result<Box<dyn AudioSource>, AudioError> {
+    let selector = env::var("LEGION_KEYBOARD_AUDIO_SOURCE").ok().filter(|selector| !selector.is_empty());
+
+    if let Some(path) = selector
+        .as_deref()
+        .filter(|selector| Path::new(selector).extension().is_some_and(|extension| extension.eq_ignore_ascii_case("wav")))
+    {
+        return Ok(Box::new(wav::WavSource::open(Path::new(path))?));
+    }
+
+    open_device(selector.as_deref())
+}
+
+#[cfg(target_os = "linux")]
+fn open_device(device: Option<&str>) -> Result<Box<dyn AudioSource>, AudioError> {
+    Ok(Box::new(pulse::PulseSource::open(device)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_device(_device: Option<&str>) -> Result<Box<dyn AudioSource>, AudioError> {
+    Err(Report::new(AudioError).attach_printable("Only WAV files can be listened to on this platform, set LEGION_KEYBOARD_AUDIO_SOURCE to the path of one"))
+}
+
+/// Keeps the latest samples of a source, read on a thread of its own so that effects never wait on it
+pub struct AudioStream {
+    samples: Arc<Mutex<VecDeque<f32>>>,
+    sample_rate: u32,
+    stop_signal: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+    /// Why the source stopped being read, if it failed after being opened
+    error_rx: Receiver<Report<AudioError>>,
+}
+
+impl AudioStream {
+    /// Start listening to the [`open_source`] one, keeping the last `capacity` samples around
+    pub fn start(capacity: usize) -> Result<Self, AudioError> {
+        let samples = Arc::new(Mutex::new(VecDeque::with_capacity(capacity + CHUNK_SIZE)));
+        let stop_signal = Arc::new(AtomicBool::new(false));
+        let (ready_tx, ready_rx) = crossbeam_channel::bounded(1);
+        let (error_tx, error_rx) = crossbeam_channel::bounded(1);
+
+        // Some sources can't be moved across threads, so it is opened on the one it is read from
+        let handle = thread::spawn({
+            let samples = samples.clone();
+            let stop_signal = stop_signal.clone();
+
+            move || {
+                let mut source = match open_source() {
+                    Ok(source) => {
+                        let _ = ready_tx.send(Ok(source.sample_rate()));
+                        source
+                    }
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(err));
+                        return;
+                    }
+                };
+
+                let mut chunk = [0.0; CHUNK_SIZE];
+
+                while !stop_signal.load(Ordering::SeqCst) {
+                    if let Err(err) = source.read(&mut chunk) {
+                        let _ = error_tx.send(err);
+                        return;
+                    }
+
+                    let mut samples = samples.lock().unwrap();
+                    samples.extend(chunk);
+
+                    let excess = samples.len().saturating_sub(capacity);
+                    samples.drain(..excess);
+                }
+            }
+        });
+
+        let sample_rate = match ready_rx.recv() {
+            Ok(result) => result?,
+            Err(err) => return Err(Report::new(err).change_context(AudioError)),
+        };
+
+        Ok(Self {
+            samples,
+            sample_rate,
+            stop_signal,
+            handle: Some(handle),
+            error_rx,
+        })
+    }
+
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    /// The error that stopped the source from being read, only returned once
+    pub fn take_error(&self) -> Option<Report<AudioError>> {
+        self.error_rx.try_recv().ok()
+    }
+
+    /// Copy the newest samples into `buffer`, the ones that weren't heard yet being silent
+    pub fn latest(&self, buffer: &mut [f32]) {
+        let samples = self.samples.lock().unwrap();
+
+        let available = samples.len().min(buffer.len());
+        let (silence, heard) = buffer.split_at_mut(buffer.len() - available);
+
+        silence.fill(0.0);
+        for (sample, latest) in heard.iter_mut().zip(samples.range(samples.len() - available..)) {
+            *sample = *latest;
+        }
+    }
+}
+
+impl Drop for AudioStream {
+    fn drop(&mut self) {
+        self.stop_signal.store(true, Ordering::SeqCst);
+
+        // Reads return after at most a chunk's worth of audio
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+    }
+}
@@ -0,0 +1,65 @@
+use error_stack::{Report, Result, ResultExt};
+use libpulse_binding::{
+    def::BufferAttr,
+    sample::{Format, Spec},
+    stream::Direction,
+};
+use libpulse_simple_binding::Simple;
+
+use super::{AudioError, AudioSource, CHUNK_SIZE};
+
+const SAMPLE_RATE: u32 = 44_100;
+
+/// The monitor of the default output, i.e. whatever is being played
+const DEFAULT_DEVICE: &str = "@DEFAULT_MONITOR@";
+
+/// Records from a PulseAudio device, which PipeWire provides as well through `pipewire-pulse`
+pub struct PulseSource {
+    simple: Simple,
+    bytes: Vec<u8>,
+}
+
+impl PulseSource {
+    pub fn open(device: Option<&str>) -> Result<Self, AudioError> {
+        let spec = Spec {
+            format: Format::F32le,
+            channels: 1,
+            rate: SAMPLE_RATE,
+        };
+
+        // Ask for small fragments to keep the latency down, the rest is left up to the server
+        let attributes = BufferAttr {
+            maxlength: u32::MAX,
+            tlength: u32::MAX,
+            prebuf: u32::MAX,
+            minreq: u32::MAX,
+            fragsize: (CHUNK_SIZE * std::mem::size_of::<f32>()) as u32,
+        };
+
+        let device = device.unwrap_or(DEFAULT_DEVICE);
+
+        let simple = Simple::new(None, env!("CARGO_PKG_NAME"), Direction::Record, Some(device), "Visualizer", &spec, None, Some(&attributes))
+            .map_err(|err| Report::new(err).change_context(AudioError))
+            .attach_printable_lazy(|| format!("Could not record from \"{device}\", is PulseAudio or PipeWire running?"))?;
+
+        Ok(Self { simple, bytes: Vec::new() })
+    }
+}
+
+impl AudioSource for PulseSource {
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+
+    fn read(&mut self, buffer: &mut [f32]) -> Result<(), AudioError> {
+        self.bytes.resize(buffer.len() * std::mem::size_of::<f32>(), 0);
+
+        self.simple.read(&mut self.bytes).map_err(|err| Report::new(err).change_context(AudioError))?;
+
+        for (sample, bytes) in buffer.iter_mut().zip(self.bytes.chunks_exact(4)) {
+            *sample = f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,79 @@
+use std::{
+    path::Path,
+    thread,
+    time::{Duration, Instant},
+};
+
+use error_stack::{Report, Result, ResultExt};
+use hound::{SampleFormat, WavReader};
+
+use super::{AudioError, AudioSource};
+
+/// Plays a file back on a loop at its normal speed, to try effects out without any sound playing
+pub struct WavSource {
+    samples: Vec<f32>,
+    sample_rate: u32,
+    position: usize,
+    started: Instant,
+    read: u64,
+}
+
+impl WavSource {
+    pub fn open(path: &Path) -> Result<Self, AudioError> {
+        let reader = WavReader::open(path)
+            .change_context(AudioError)
+            .attach_printable_lazy(|| format!("Could not open {}", path.display()))?;
+
+        let spec = reader.spec();
+        let channels = usize::from(spec.channels).max(1);
+
+        let interleaved: std::result::Result<Vec<f32>, hound::Error> = match spec.sample_format {
+            SampleFormat::Float => reader.into_samples::<f32>().collect(),
+            SampleFormat::Int => {
+                let scale = 1.0 / (1_i64 << (spec.bits_per_sample - 1)) as f32;
+                reader.into_samples::<i32>().map(|sample| sample.map(|sample| sample as f32 * scale)).collect()
+            }
+        };
+
+        let samples: Vec<f32> = interleaved
+            .change_context(AudioError)
+            .attach_printable_lazy(|| format!("Could not read {}", path.display()))?
+            .chunks_exact(channels)
+            .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+            .collect();
+
+        if samples.is_empty() {
+            return Err(Report::new(AudioError).attach_printable(format!("{} has no samples", path.display())));
+        }
+
+        Ok(Self {
+            samples,
+            sample_rate: spec.sample_rate,
+            position: 0,
+            started: Instant::now(),
+            read: 0,
+        })
+    }
+}
+
+impl AudioSource for WavSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn read(&mut self, buffer: &mut [f32]) -> Result<(), AudioError> {
+        // Hand the samples out no faster than a live source would
+        self.read += buffer.len() as u64;
+        let due = Duration::from_secs_f64(self.read as f64 / f64::from(self.sample_rate));
+        if let Some(wait) = due.checked_sub(self.started.elapsed()) {
+            thread::sleep(wait);
+        }
+
+        for sample in buffer {
+            *sample = self.samples[self.position];
+            self.position = (self.position + 1) % self.samples.len();
+        }
+
+        Ok(())
+    }
+}
@@ -1,13 +1,31 @@
-use std::{convert::TryInto, path::PathBuf, process, str::FromStr};
+use std::{
+    convert::TryInto,
+    env, fs,
+    path::{Path, PathBuf},
+    process::ExitCode,
+    str::FromStr,
+    sync::{atomic::AtomicBool, Arc},
+    thread,
+    time::Instant,
+};
 
 use clap::{arg, command, Parser, Subcommand};
-use error_stack::{Result, ResultExt};
-use strum::IntoEnumIterator;
+use crossbeam_channel::Receiver;
+use error_stack::{Report, Result, ResultExt};
+use legion_rgb_driver::{
+    capture,
+    device::{self, DeviceDatabase, DeviceSelector},
+    error::Error as DriverError,
+    transport::MockTransport,
+    Keyboard, LightingState,
+};
 use thiserror::Error;
 
 use crate::{
-    effects::{self, custom_effect::CustomEffect, ManagerCreationError},
-    enums::{Brightness, Direction, Effects},
+    diagnostics,
+    effects::{self, custom_effect::CustomEffect, registry, ManagerCreationError},
+    enums::{Brightness, Direction, Effects, ExitBehavior},
+    persist::Settings,
     profile::{self, Profile},
 };
 
@@ -39,6 +57,23 @@ struct Cli {
     /// Do not show the window when launching (use along the --gui flag)
     #[arg(short = 'w', long, default_value_t = false)]
     hide_window: bool,
+
+    /// The keyboard to use when more than one is connected, as listed by "list-devices". Example: pid:c993 or serial:ABC123
+    ///
+    /// A vendor and product id pair such as 048d:c999 also allows using a model missing from the device database.
+    /// Defaults to the LEGION_KEYBOARD_DEVICE environment variable
+    #[arg(long, global = true)]
+    device: Option<DeviceSelector>,
+
+    /// A JSON file with extra keyboard models to support, in the same format as the built-in list. Defaults to the LEGION_KEYBOARD_DEVICES environment variable
+    #[arg(long, global = true)]
+    device_database: Option<PathBuf>,
+
+    /// What to leave the keyboard showing once the program exits: keep, restore (the state found on startup), off or the path to a profile file
+    ///
+    /// Defaults to the LEGION_KEYBOARD_ON_EXIT environment variable, then to the setting saved by the GUI
+    #[arg(long, global = true, value_parser = parse_exit_behavior)]
+    on_exit: Option<ExitBehavior>,
 }
 
 #[derive(Subcommand)]
@@ -61,6 +96,10 @@ enum Commands {
         #[arg(short, long, default_value = "Low", value_parser)]
         brightness: Brightness,
 
+        /// Dim the colors further, as a percentage of the chosen brightness (does not apply to Smooth and Wave)
+        #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u8).range(0..=100))]
+        software_brightness: u8,
+
         /// The speed of the effect
         #[arg(short, long, default_value_t = 1, value_parser = clap_value_parser!(["1","2","3","4","5"], u8))]
         speed: u8,
@@ -77,17 +116,57 @@ enum Commands {
     /// List all the available effects
     List,
 
+    /// List the supported keyboards that are currently connected
+    ListDevices,
+
     /// Load a profile from a file
     LoadProfile {
         #[arg(short, long)]
         path: PathBuf,
     },
 
-    /// Load a custom effect from a file
+    /// Load a custom effect from a file, either a JSON list of steps or a Rhai script ending in .rhai
     CustomEffect {
         #[arg(short, long)]
         path: PathBuf,
     },
+
+    /// Check profiles and custom effects for mistakes without applying them
+    Validate {
+        /// The files to check, profiles and custom effects are told apart by their contents
+        #[arg(required = true)]
+        paths: Vec<PathBuf>,
+    },
+
+    /// Generate the udev rules needed to use the connected keyboard without root and install them
+    #[cfg(target_os = "linux")]
+    Udev {
+        /// Generate the rules for every known model instead of only the connected ones
+        #[arg(long, default_value_t = false)]
+        all: bool,
+
+        /// Where to write the rules to
+        #[arg(short, long, default_value = crate::udev::DEFAULT_RULES_PATH)]
+        output: PathBuf,
+
+        /// Reload the udev rules once written so they apply without rebooting
+        #[arg(long, default_value_t = false)]
+        reload: bool,
+
+        /// Only print the rules instead of writing them
+        #[arg(long, default_value_t = false)]
+        dry_run: bool,
+    },
+
+    /// Replay a payload capture recorded through LEGION_KEYBOARD_CAPTURE, printing each decoded payload
+    Replay {
+        #[arg(short, long)]
+        path: PathBuf,
+
+        /// Send the payloads to an in-memory keyboard instead of the real one, without waiting between them
+        #[arg(long, default_value_t = false)]
+        mock: bool,
+    },
 }
 
 fn parse_colors(arg: &str) -> std::result::Result<[u8; 12], String> {
@@ -108,6 +187,20 @@ fn parse_colors(arg: &str) -> std::result::Result<[u8; 12], String> {
     }
 }
 
+fn parse_exit_behavior(arg: &str) -> std::result::Result<ExitBehavior, String> {
+    match arg.to_lowercase().as_str() {
+        "keep" => Ok(ExitBehavior::Keep),
+        "restore" => Ok(ExitBehavior::Restore),
+        "off" => Ok(ExitBehavior::Off),
+        _ => Profile::load_profile(Path::new(arg)).map(ExitBehavior::Profile).map_err(|err| {
+            format!(
+                "Expected keep, restore, off or the path to a profile file, could not load a profile from \"{arg}\": {}",
+                diagnostics::summary(&err)
+            )
+        }),
+    }
+}
+
 pub enum CliOutput {
     /// Start the UI
     Gui { hide_window: bool, output: OutputType },
@@ -128,10 +221,16 @@ impl CliOutput {
 
 pub enum GuiCommand {
     /// Start the UI
-    Start { hide_window: bool, output: OutputType },
+    Start {
+        hide_window: bool,
+        device: Option<DeviceSelector>,
+        /// Overrides the saved setting when set
+        exit_behavior: Option<ExitBehavior>,
+        output: OutputType,
+    },
 
     /// Close the program as the CLI was invoked
-    Exit,
+    Exit(ExitCode),
 }
 
 /// What instruction was received through the CLI
@@ -139,20 +238,49 @@ pub enum OutputType {
     Profile(Profile),
     Custom(CustomEffect),
     NoArgs,
-    Exit,
+    /// Nothing to play, leave with the given status
+    Exit(ExitCode),
 }
 
 #[derive(Debug, Error)]
 #[error("There was an error while executing the CLI")]
 pub struct CliError;
 
-pub fn try_cli() -> Result<GuiCommand, CliError> {
-    let output = parse_cli()?;
+pub fn try_cli(termination_rx: &Receiver<()>) -> Result<GuiCommand, CliError> {
+    let mut cli = Cli::parse();
+
+    if cli.device.is_none() {
+        cli.device = device_from_env()?;
+    }
+
+    if cli.on_exit.is_none() {
+        cli.on_exit = exit_behavior_from_env()?;
+    }
+
+    load_device_database(cli.device_database.as_deref(), cli.device.as_ref())?;
+
+    let device = cli.device.clone();
+    let exit_behavior = cli.on_exit.clone();
+
+    let output = parse_cli(cli)?;
 
     match output {
-        CliOutput::Gui { hide_window, output } => Ok(GuiCommand::Start { hide_window, output }),
+        CliOutput::Gui { hide_window, output } => Ok(GuiCommand::Start {
+            hide_window,
+            device,
+            exit_behavior,
+            output,
+        }),
+        // Nothing left to do with the keyboard
+        CliOutput::Cli(OutputType::Exit(code)) => Ok(GuiCommand::Exit(code)),
         CliOutput::Cli(output) => {
-            let manager_result = effects::EffectManager::new(effects::OperationMode::Cli);
+            let manager_result = effects::EffectManager::new(effects::OperationMode::Cli, device.as_ref());
+
+            if let Some(err @ DriverError::PermissionDenied { .. }) = manager_result.as_ref().err().and_then(|err| err.downcast_ref::<DriverError>()) {
+                println!("{err}");
+                println!("Then reload the rules with: sudo udevadm control --reload-rules && sudo udevadm trigger");
+                return Ok(GuiCommand::Exit(ExitCode::FAILURE));
+            }
 
             let instance_not_unique = if let Err(err) = &manager_result {
                 &ManagerCreationError::InstanceAlreadyRunning == err.current_context()
@@ -164,33 +292,79 @@ pub fn try_cli() -> Result<GuiCommand, CliError> {
             if let OutputType::Profile(..) | OutputType::Custom(..) = output {
                 if instance_not_unique {
                     println!("Another instance of the program is already running, please close it before starting a new one.");
-                    process::exit(0);
+                    return Ok(GuiCommand::Exit(ExitCode::SUCCESS));
                 }
             }
 
             let mut effect_manager = manager_result.unwrap();
+            let exit_behavior = exit_behavior.unwrap_or_else(|| Settings::load().exit_behavior);
+            if exit_behavior == ExitBehavior::Restore && effect_manager.hardware_state().is_none() {
+                println!("This keyboard can't report what it was showing on startup, it will be left on the last frame instead of being restored.");
+            }
+            effect_manager.set_exit_behavior(exit_behavior);
+            effect_manager.stop_on(termination_rx.clone());
 
             match output {
                 OutputType::Profile(profile) => {
                     effect_manager.set_profile(profile);
                     effect_manager.join_and_exit();
-                    Ok(GuiCommand::Exit)
+                    Ok(GuiCommand::Exit(ExitCode::SUCCESS))
                 }
                 OutputType::Custom(effect) => {
                     effect_manager.custom_effect(effect);
                     effect_manager.join_and_exit();
-                    Ok(GuiCommand::Exit)
+                    Ok(GuiCommand::Exit(ExitCode::SUCCESS))
                 }
-                OutputType::Exit => Ok(GuiCommand::Exit),
+                OutputType::Exit(_) => unreachable!("Exiting is handled before acquiring the keyboard"),
                 OutputType::NoArgs => unreachable!("No arguments were provided but the app is in CLI mode"),
             }
         }
     }
 }
 
-fn parse_cli() -> Result<CliOutput, CliError> {
-    let cli = Cli::parse();
+fn device_from_env() -> Result<Option<DeviceSelector>, CliError> {
+    match env::var("LEGION_KEYBOARD_DEVICE") {
+        Ok(selector) => selector
+            .parse()
+            .map(Some)
+            .map_err(|err: String| Report::new(CliError).attach_printable(format!("Invalid LEGION_KEYBOARD_DEVICE: {err}"))),
+        Err(_) => Ok(None),
+    }
+}
+
+fn exit_behavior_from_env() -> Result<Option<ExitBehavior>, CliError> {
+    match env::var("LEGION_KEYBOARD_ON_EXIT") {
+        Ok(exit_behavior) => parse_exit_behavior(&exit_behavior)
+            .map(Some)
+            .map_err(|err| Report::new(CliError).attach_printable(format!("Invalid LEGION_KEYBOARD_ON_EXIT: {err}"))),
+        Err(_) => Ok(None),
+    }
+}
+
+/// Merge the user's device database into the built-in one and make it the one used to look for keyboards
+fn load_device_database(path: Option<&Path>, device: Option<&DeviceSelector>) -> Result<(), CliError> {
+    let mut database = DeviceDatabase::builtin();
+
+    let path = path.map(Path::to_path_buf).or_else(|| env::var_os("LEGION_KEYBOARD_DEVICES").map(PathBuf::from));
+
+    if let Some(path) = path {
+        database
+            .merge_file(&path)
+            .change_context(CliError)
+            .attach_printable_lazy(|| format!("Could not load the device database at {}", path.display()))?;
+    }
+
+    if let Some(DeviceSelector::VendorProductId(vendor_id, product_id)) = device {
+        database.add_override(*vendor_id, *product_id);
+    }
+
+    // Should nothing have looked up a keyboard yet, the built-in database would be used without the user's additions
+    device::set_database(database).map_err(|_| Report::new(CliError).attach_printable("The device database was already in use before it could be loaded"))?;
+
+    Ok(())
+}
 
+fn parse_cli(cli: Cli) -> Result<CliOutput, CliError> {
     let Some(subcommand) = cli.command else {
         let exec_name = std::env::current_exe().unwrap().file_name().unwrap().to_string_lossy().into_owned();
         println!("No subcommands found, starting in GUI mode. To view the possible subcommands type \"{exec_name} --help\".",);
@@ -202,17 +376,17 @@ fn parse_cli() -> Result<CliOutput, CliError> {
             effect,
             colors,
             brightness,
+            software_brightness,
             speed,
             direction,
             save,
         } => {
+            // Variants holding settings come out of the parser zeroed, start from the effect's defaults instead
+            let effect = registry::find(effect).kind();
             let direction = direction.unwrap_or_default();
 
             let rgb_array: [u8; 12] = if effect.takes_color_array() {
-                colors.unwrap_or_else(|| {
-                    println!("This effect requires specifying the colors to use.");
-                    process::exit(0);
-                })
+                colors.ok_or_else(|| Report::new(CliError).attach_printable("This effect requires specifying the colors to use."))?
             } else {
                 [0; 12]
             };
@@ -224,6 +398,8 @@ fn parse_cli() -> Result<CliOutput, CliError> {
                 direction,
                 speed,
                 brightness,
+                software_brightness,
+                layers: Vec::new(),
             };
 
             if let Some(filename) = save {
@@ -234,11 +410,26 @@ fn parse_cli() -> Result<CliOutput, CliError> {
         }
         Commands::List => {
             println!("List of available effects:");
-            for (i, effect) in Effects::iter().enumerate() {
-                println!("{}. {effect}", i + 1);
+            for (i, effect) in registry::EFFECTS.iter().enumerate() {
+                println!("{}. {}: {}", i + 1, effect.name(), effect.info().description);
             }
 
-            Ok(CliOutput::maybe_gui(false, cli.hide_window, OutputType::Exit))
+            Ok(CliOutput::maybe_gui(false, cli.hide_window, OutputType::Exit(ExitCode::SUCCESS)))
+        }
+
+        Commands::ListDevices => {
+            let keyboards = device::list_keyboards().change_context(CliError)?;
+
+            if keyboards.is_empty() {
+                println!("No supported keyboards found.");
+            } else {
+                println!("List of connected keyboards:");
+                for (i, keyboard) in keyboards.iter().enumerate() {
+                    println!("{}. {keyboard}", i + 1);
+                }
+            }
+
+            Ok(CliOutput::maybe_gui(false, cli.hide_window, OutputType::Exit(ExitCode::SUCCESS)))
         }
 
         Commands::LoadProfile { path } => {
@@ -252,5 +443,124 @@ fn parse_cli() -> Result<CliOutput, CliError> {
 
             Ok(CliOutput::maybe_gui(cli.gui, cli.hide_window, OutputType::Custom(effect)))
         }
+
+        Commands::Validate { paths } => {
+            let mut invalid = 0;
+
+            for path in &paths {
+                match validate_file(path) {
+                    Ok(kind) => println!("{}: OK ({kind})", path.display()),
+                    Err(problems) => {
+                        invalid += 1;
+                        for problem in problems {
+                            println!("{problem}");
+                        }
+                    }
+                }
+            }
+
+            let code = if invalid > 0 {
+                println!("{invalid} of {} files have errors.", paths.len());
+                ExitCode::FAILURE
+            } else {
+                ExitCode::SUCCESS
+            };
+
+            Ok(CliOutput::maybe_gui(false, cli.hide_window, OutputType::Exit(code)))
+        }
+
+        #[cfg(target_os = "linux")]
+        Commands::Udev { all, output, reload, dry_run } => {
+            let rules = crate::udev::generate_rules(all, cli.device.as_ref()).change_context(CliError)?;
+
+            if dry_run {
+                print!("{rules}");
+            } else {
+                crate::udev::write_rules(&rules, &output).change_context(CliError)?;
+                println!("Wrote the udev rules to {}", output.display());
+
+                if reload {
+                    crate::udev::reload_rules().change_context(CliError)?;
+                    println!("Reloaded the udev rules, you may need to reconnect the keyboard or reboot if it still can't be accessed.");
+                } else {
+                    println!("Run \"sudo udevadm control --reload-rules && sudo udevadm trigger\" or reboot for them to apply.");
+                }
+            }
+
+            Ok(CliOutput::maybe_gui(false, cli.hide_window, OutputType::Exit(ExitCode::SUCCESS)))
+        }
+
+        Commands::Replay { path, mock } => {
+            replay_capture(&path, cli.device.as_ref(), mock)?;
+
+            Ok(CliOutput::maybe_gui(false, cli.hide_window, OutputType::Exit(ExitCode::SUCCESS)))
+        }
     }
 }
+
+/// What kind of file it is if it is valid, every problem found otherwise
+fn validate_file(path: &Path) -> std::result::Result<&'static str, Vec<String>> {
+    fn problems<C: error_stack::Context>(path: &Path, err: &Report<C>) -> Vec<String> {
+        let found = diagnostics::find(err);
+
+        if found.is_empty() {
+            vec![format!("{}: {}", path.display(), err.current_context())]
+        } else {
+            found.into_iter().map(ToString::to_string).collect()
+        }
+    }
+
+    // Profiles are the only files with zones at the top level
+    let is_profile = fs::read_to_string(path)
+        .ok()
+        .and_then(|json| serde_json::from_str::<serde_json::Value>(&json).ok())
+        .is_some_and(|value| value.get("rgb_zones").is_some());
+
+    if is_profile {
+        return Profile::load_profile(path).map(|_| "profile").map_err(|err| problems(path, &err));
+    }
+
+    match CustomEffect::from_file(path) {
+        Ok(CustomEffect::Steps(_)) => Ok("custom effect"),
+        Ok(CustomEffect::Keyframes(_)) => Ok("custom effect, version 2"),
+        Ok(CustomEffect::Script(_)) => Ok("script"),
+        Err(err) => Err(problems(path, &err)),
+    }
+}
+
+fn replay_capture(path: &Path, device: Option<&DeviceSelector>, mock: bool) -> Result<(), CliError> {
+    let captured = capture::read_capture(path).change_context(CliError)?;
+
+    let stop_signal = Arc::new(AtomicBool::new(false));
+    let keyboard_result = if mock {
+        Keyboard::with_transport(MockTransport::new(), stop_signal)
+    } else if let Some(selector) = device {
+        legion_rgb_driver::select_keyboard(selector, stop_signal)
+    } else {
+        legion_rgb_driver::get_keyboard(stop_signal)
+    };
+    let mut keyboard = keyboard_result.change_context(CliError)?;
+
+    let capture_start = captured.first().map(|entry| entry.timestamp).unwrap_or_default();
+    let replay_start = Instant::now();
+
+    for entry in captured {
+        let offset = entry.timestamp.saturating_sub(capture_start);
+
+        if !mock {
+            if let Some(wait) = offset.checked_sub(replay_start.elapsed()) {
+                thread::sleep(wait);
+            }
+        }
+
+        match LightingState::from_payload(&entry.payload) {
+            Ok(state) => {
+                println!("{:>8}ms {state}", offset.as_millis());
+                keyboard.send_payload(&entry.payload).change_context(CliError)?;
+            }
+            Err(err) => println!("{:>8}ms Skipped: {err}", offset.as_millis()),
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,205 @@
+use std::{
+    fmt,
+    path::{Path, PathBuf},
+};
+
+use error_stack::{Context, Report};
+
+/// A problem found in a profile or custom effect, pointing at where it is in the file when known
+#[derive(Clone, Debug)]
+pub struct Diagnostic {
+    pub file: Option<PathBuf>,
+    /// The line and column, both starting at 1
+    pub position: Option<(usize, usize)>,
+    /// The field at fault, e.g. `effect_steps[2].brightness`
+    pub field: Option<String>,
+    pub message: String,
+}
+
+impl Diagnostic {
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            file: None,
+            position: None,
+            field: None,
+            message: message.into(),
+        }
+    }
+
+    pub fn at_field(field: impl Into<String>, message: impl Into<String>) -> Self {
+        Self {
+            field: Some(field.into()),
+            ..Self::new(message)
+        }
+    }
+
+    pub fn at_position(line: usize, column: usize, message: impl Into<String>) -> Self {
+        Self {
+            position: Some((line, column)),
+            ..Self::new(message)
+        }
+    }
+
+    /// serde_json puts the position at the end of its messages, it is kept apart instead
+    pub fn from_json_error(err: &serde_json::Error) -> Self {
+        let message = err.to_string();
+
+        if err.line() == 0 {
+            return Self::new(message);
+        }
+
+        let suffix = format!(" at line {} column {}", err.line(), err.column());
+        let message = message.strip_suffix(&suffix).map_or_else(|| message.clone(), str::to_string);
+
+        Self::at_position(err.line(), err.column(), message)
+    }
+
+    /// Point at the same field within `parent`, e.g. `speed` becomes `layers[1].speed`
+    #[must_use]
+    pub fn nested(self, parent: &str) -> Self {
+        let field = match &self.field {
+            Some(field) => format!("{parent}.{field}"),
+            None => parent.to_string(),
+        };
+
+        Self { field: Some(field), ..self }
+    }
+
+    #[must_use]
+    pub fn in_file(self, file: &Path) -> Self {
+        Self {
+            file: Some(file.to_path_buf()),
+            ..self
+        }
+    }
+
+    /// The diagnostic without the file, for when it is already obvious
+    pub fn describe(&self) -> String {
+        match (&self.position, &self.field) {
+            (Some((line, column)), _) => format!("line {line}, column {column}: {}", self.message),
+            (None, Some(field)) => format!("{field}: {}", self.message),
+            (None, None) => self.message.clone(),
+        }
+    }
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(file) = &self.file else {
+            return f.write_str(&self.describe());
+        };
+
+        match (&self.position, &self.field) {
+            (Some((line, column)), _) => write!(f, "{}:{line}:{column}: {}", file.display(), self.message),
+            (None, Some(field)) => write!(f, "{}: {field}: {}", file.display(), self.message),
+            (None, None) => write!(f, "{}: {}", file.display(), self.message),
+        }
+    }
+}
+
+/// Build a report carrying every diagnostic, which [`find`] gets back
+pub fn report<C: Context>(context: C, diagnostics: Vec<Diagnostic>) -> Report<C> {
+    diagnostics.into_iter().fold(Report::new(context), |report, diagnostic| report.attach_printable(diagnostic))
+}
+
+/// The diagnostics attached anywhere in the report, in the order they were found
+pub fn find<C>(report: &Report<C>) -> Vec<&Diagnostic> {
+    let mut diagnostics: Vec<&Diagnostic> = report.frames().filter_map(|frame| frame.downcast_ref::<Diagnostic>()).collect();
+    diagnostics.reverse();
+
+    diagnostics
+}
+
+/// A short explanation of why loading failed, for places with little room like the GUI toasts
+pub fn summary<C: Context>(report: &Report<C>) -> String {
+    let diagnostics = find(report);
+
+    match diagnostics.as_slice() {
+        [] => report.current_context().to_string(),
+        [diagnostic] => diagnostic.describe(),
+        [diagnostic, rest @ ..] => format!("{} (and {} more)", diagnostic.describe(), rest.len()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+    use thiserror::Error;
+
+    use super::*;
+
+    #[derive(Debug, Error)]
+    #[error("Could not load the file")]
+    struct LoadError;
+
+    #[derive(Debug, Deserialize)]
+    struct Speed {
+        #[allow(dead_code)]
+        speed: u8,
+    }
+
+    fn messages(diagnostics: &[&Diagnostic]) -> Vec<String> {
+        diagnostics.iter().map(|diagnostic| diagnostic.message.clone()).collect()
+    }
+
+    #[test]
+    fn json_errors_keep_their_position_apart() {
+        let err = serde_json::from_str::<Speed>("{\n  \"speed\": 300\n}").unwrap_err();
+        let diagnostic = Diagnostic::from_json_error(&err);
+
+        assert_eq!(diagnostic.message, "invalid value: integer `300`, expected u8");
+        assert_eq!(diagnostic.position, Some((2, 14)));
+        assert_eq!(diagnostic.field, None);
+        assert_eq!(
+            diagnostic.in_file(Path::new("profile.json")).to_string(),
+            "profile.json:2:14: invalid value: integer `300`, expected u8"
+        );
+    }
+
+    #[test]
+    fn json_errors_without_a_position() {
+        let err = serde_json::from_value::<Speed>(serde_json::Value::Bool(true)).unwrap_err();
+        let diagnostic = Diagnostic::from_json_error(&err);
+
+        assert_eq!(diagnostic.message, err.to_string());
+        assert_eq!(diagnostic.position, None);
+    }
+
+    #[test]
+    fn fields_are_nested() {
+        assert_eq!(Diagnostic::at_field("speed", "too fast").nested("layers[1]").field.as_deref(), Some("layers[1].speed"));
+        assert_eq!(Diagnostic::new("too fast").nested("effect_steps[2]").field.as_deref(), Some("effect_steps[2]"));
+        assert_eq!(
+            Diagnostic::at_field("speed", "too fast").nested("layers[1]").nested("effect_steps[0]").field.as_deref(),
+            Some("effect_steps[0].layers[1].speed")
+        );
+    }
+
+    #[test]
+    fn diagnostics_are_displayed_with_where_they_are() {
+        let file = Path::new("effect.json");
+
+        assert_eq!(Diagnostic::at_field("speed", "too fast").to_string(), "speed: too fast");
+        assert_eq!(Diagnostic::at_field("speed", "too fast").in_file(file).to_string(), "effect.json: speed: too fast");
+        assert_eq!(Diagnostic::at_position(3, 7, "unexpected").describe(), "line 3, column 7: unexpected");
+        assert_eq!(Diagnostic::new("empty").in_file(file).to_string(), "effect.json: empty");
+    }
+
+    #[test]
+    fn reports_carry_every_diagnostic_in_order() {
+        let report = report(LoadError, vec![Diagnostic::new("first"), Diagnostic::new("second"), Diagnostic::new("third")]);
+
+        assert_eq!(messages(&find(&report)), ["first", "second", "third"]);
+        assert_eq!(messages(&find(&report.change_context(LoadError))), ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn summaries() {
+        assert_eq!(summary(&report(LoadError, Vec::new())), "Could not load the file");
+        assert_eq!(summary(&report(LoadError, vec![Diagnostic::at_field("speed", "too fast")])), "speed: too fast");
+        assert_eq!(
+            summary(&report(LoadError, vec![Diagnostic::at_position(1, 2, "first"), Diagnostic::new("second"), Diagnostic::new("third")])),
+            "line 1, column 2: first (and 2 more)"
+        );
+    }
+}
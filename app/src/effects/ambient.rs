@@ -1,15 +1,18 @@
-use std::{
-    num::NonZeroU32,
-    sync::atomic::Ordering,
-    thread,
-    time::{Duration, Instant},
-};
+use std::num::NonZeroU32;
 
 use fast_image_resize as fr;
 
 use fr::Resizer;
+use legion_rgb_driver::color::ZoneColors;
 use scrap::{Capturer, Display, Frame, TraitCapturer};
 
+use crate::{enums::Effects, profile::Profile};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter},
+    render::{Renderer, Tick},
+};
+
 #[derive(Clone, Copy)]
 struct ScreenDimensions {
     src: (NonZeroU32, NonZeroU32),
@@ -18,68 +21,105 @@ struct ScreenDimensions {
 
 pub(super) struct AmbientLight;
 
-impl AmbientLight {
-    pub fn play(manager: &mut super::Inner, fps: u8, saturation_boost: f32) {
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            //Display setup
-            let display = Display::all().unwrap().remove(0);
+struct AmbientLightRenderer {
+    capturer: Capturer,
+    dimensions: ScreenDimensions,
+    resizer: Resizer,
+    fps: u8,
+    saturation_boost: f32,
+    #[cfg(target_os = "windows")]
+    try_gdi: u8,
+}
 
-            let mut capturer = Capturer::new(display, false).expect("Couldn't begin capture.");
+impl AmbientLightRenderer {
+    fn new(fps: u8, saturation_boost: f32) -> Self {
+        //Display setup
+        let display = Display::all().unwrap().remove(0);
 
-            let dimensions = ScreenDimensions {
-                src: (NonZeroU32::new(capturer.width() as u32).unwrap(), NonZeroU32::new(capturer.height() as u32).unwrap()),
-                dest: (NonZeroU32::new(4).unwrap(), NonZeroU32::new(1).unwrap()),
-            };
+        let capturer = Capturer::new(display, false).expect("Couldn't begin capture.");
 
-            let seconds_per_frame = Duration::from_nanos(1_000_000_000 / u64::from(fps));
-            let mut resizer = fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Box));
+        let dimensions = ScreenDimensions {
+            src: (NonZeroU32::new(capturer.width() as u32).unwrap(), NonZeroU32::new(capturer.height() as u32).unwrap()),
+            dest: (NonZeroU32::new(4).unwrap(), NonZeroU32::new(1).unwrap()),
+        };
 
+        Self {
+            capturer,
+            dimensions,
+            resizer: fr::Resizer::new(fr::ResizeAlg::Convolution(fr::FilterType::Box)),
+            fps,
+            saturation_boost,
             #[cfg(target_os = "windows")]
-            let mut try_gdi = 1;
-
-            while !manager.stop_signals.keyboard_stop_signal.load(Ordering::SeqCst) {
-                let now = Instant::now();
-
-                #[allow(clippy::single_match)]
-                match capturer.frame(seconds_per_frame) {
-                    Ok(frame) => {
-                        let rgb = process_frame(&frame, dimensions, &mut resizer, saturation_boost);
+            try_gdi: 1,
+        }
+    }
+}
 
-                        manager.keyboard.set_colors_to(&rgb).unwrap();
-                        #[cfg(target_os = "windows")]
-                        {
-                            try_gdi = 0;
+impl Renderer for AmbientLightRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        // Keep showing the last frame until the screen changes
+        let mut colors = tick.previous;
+
+        // Wait for a new frame for at most a tick
+        #[allow(clippy::single_match)]
+        match self.capturer.frame(tick.interval) {
+            Ok(frame) => {
+                colors = ZoneColors::from_array(process_frame(&frame, self.dimensions, &mut self.resizer, self.saturation_boost));
+                #[cfg(target_os = "windows")]
+                {
+                    self.try_gdi = 0;
+                }
+            }
+            Err(error) => match error.kind() {
+                std::io::ErrorKind::WouldBlock =>
+                {
+                    #[cfg(target_os = "windows")]
+                    if self.try_gdi > 0 && !self.capturer.is_gdi() {
+                        if self.try_gdi > 3 {
+                            self.capturer.set_gdi();
+                            self.try_gdi = 0;
                         }
+                        self.try_gdi += 1;
                     }
-                    Err(error) => match error.kind() {
-                        std::io::ErrorKind::WouldBlock =>
-                        {
-                            #[cfg(target_os = "windows")]
-                            if try_gdi > 0 && !capturer.is_gdi() {
-                                if try_gdi > 3 {
-                                    capturer.set_gdi();
-                                    try_gdi = 0;
-                                }
-                                try_gdi += 1;
-                            }
-                        }
-                        _ =>
-                        {
-                            #[cfg(windows)]
-                            if !capturer.is_gdi() {
-                                capturer.set_gdi();
-                                continue;
-                            }
-                        }
-                    },
                 }
-
-                let elapsed_time = now.elapsed();
-                if elapsed_time < seconds_per_frame {
-                    thread::sleep(seconds_per_frame - elapsed_time);
+                _ =>
+                {
+                    #[cfg(windows)]
+                    if !self.capturer.is_gdi() {
+                        self.capturer.set_gdi();
+                    }
                 }
-            }
+            },
         }
+
+        colors
+    }
+
+    fn tick_rate(&self) -> Option<u32> {
+        Some(u32::from(self.fps))
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "The colors at the edges of the screen",
+    parameters: &[Parameter::Fps(1..=60), Parameter::SaturationBoost],
+};
+
+impl Effect for AmbientLight {
+    fn kind(&self) -> Effects {
+        Effects::AmbientLight { fps: 30, saturation_boost: 0.0 }
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        let Effects::AmbientLight { fps, saturation_boost } = profile.effect else {
+            unreachable!("The ambient light effect was given another effect's profile")
+        };
+
+        Some(Box::new(AmbientLightRenderer::new(fps.clamp(1, 60), saturation_boost.clamp(0.0, 1.0))))
     }
 }
 
@@ -0,0 +1,115 @@
+use legion_rgb_driver::{color::ZoneColors, BaseEffects, SPEED_RANGE};
+
+use crate::{
+    enums::{Direction, Effects},
+    profile::Profile,
+};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter},
+    render::{Renderer, Tick},
+};
+
+static STATIC: EffectInfo = EffectInfo {
+    description: "A single color per zone",
+    parameters: &[Parameter::Colors],
+};
+
+static BREATH: EffectInfo = EffectInfo {
+    description: "The zone colors slowly fading in and out",
+    parameters: &[Parameter::Colors, Parameter::Speed(SPEED_RANGE)],
+};
+
+static SMOOTH: EffectInfo = EffectInfo {
+    description: "The whole keyboard cycling through the rainbow",
+    parameters: &[Parameter::Speed(SPEED_RANGE)],
+};
+
+static WAVE: EffectInfo = EffectInfo {
+    description: "A rainbow moving across the keyboard",
+    parameters: &[Parameter::Speed(SPEED_RANGE), Parameter::Direction],
+};
+
+pub(super) struct Static;
+
+impl Effect for Static {
+    fn kind(&self) -> Effects {
+        Effects::Static
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &STATIC
+    }
+
+    fn hardware_effect(&self, _direction: Direction) -> Option<BaseEffects> {
+        Some(BaseEffects::Static)
+    }
+
+    fn layer_renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(StaticRenderer(profile.zone_colors())))
+    }
+
+    fn can_be_layered(&self) -> bool {
+        true
+    }
+}
+
+/// Static drawn by the app, so that it can be layered
+struct StaticRenderer(ZoneColors);
+
+impl Renderer for StaticRenderer {
+    fn render(&mut self, _tick: &Tick) -> ZoneColors {
+        self.0
+    }
+}
+
+pub(super) struct Breath;
+
+impl Effect for Breath {
+    fn kind(&self) -> Effects {
+        Effects::Breath
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &BREATH
+    }
+
+    fn hardware_effect(&self, _direction: Direction) -> Option<BaseEffects> {
+        Some(BaseEffects::Breath)
+    }
+}
+
+pub(super) struct Smooth;
+
+impl Effect for Smooth {
+    fn kind(&self) -> Effects {
+        Effects::Smooth
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &SMOOTH
+    }
+
+    fn hardware_effect(&self, _direction: Direction) -> Option<BaseEffects> {
+        Some(BaseEffects::Smooth)
+    }
+}
+
+pub(super) struct Wave;
+
+impl Effect for Wave {
+    fn kind(&self) -> Effects {
+        Effects::Wave
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &WAVE
+    }
+
+    fn hardware_effect(&self, direction: Direction) -> Option<BaseEffects> {
+        match direction {
+            Direction::Left => Some(BaseEffects::LeftWave),
+            Direction::Right => Some(BaseEffects::RightWave),
+        }
+    }
+}
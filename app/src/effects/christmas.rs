@@ -1,97 +1,129 @@
-use std::{sync::atomic::Ordering, thread, time::Duration};
+use std::time::Duration;
 
-use rand::Rng;
+use legion_rgb_driver::color::{Rgb, ZoneColors, ZoneId};
+use rand::{rngs::ThreadRng, thread_rng, Rng};
+
+use crate::{enums::Effects, profile::Profile};
+
+use super::{
+    registry::{Effect, EffectInfo},
+    render::{self, Renderer, Tick, Timeline},
+};
+
+const XMAS_COLORS: [Rgb; 4] = [Rgb::new(255, 10, 10), Rgb::new(255, 255, 20), Rgb::new(30, 255, 30), Rgb::new(70, 70, 255)];
+const SUBEFFECT_COUNT: usize = 4;
 
 pub(super) struct Christmas;
 
-impl Christmas {
-    pub fn play(manager: &mut super::Inner, thread_rng: &mut rand::rngs::ThreadRng) {
-        let xmas_color_array = [[255, 10, 10], [255, 255, 20], [30, 255, 30], [70, 70, 255]];
-        let subeffect_count = 4;
-        let mut last_subeffect = -1;
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            let mut subeffect = thread_rng.gen_range(0..subeffect_count);
-            while last_subeffect == subeffect {
-                subeffect = thread_rng.gen_range(0..subeffect_count);
-            }
-            last_subeffect = subeffect;
-
-            match subeffect {
-                0 => {
-                    for _i in 0..3 {
-                        for colors in xmas_color_array {
-                            manager.keyboard.solid_set_colors_to(colors).unwrap();
-                            thread::sleep(Duration::from_millis(500));
-                        }
+struct ChristmasRenderer {
+    timeline: Timeline,
+    last_subeffect: Option<usize>,
+    thread_rng: ThreadRng,
+}
+
+impl ChristmasRenderer {
+    /// Queue up another one of the patterns, never the same twice in a row
+    fn queue_subeffect(&mut self) {
+        let mut subeffect = self.thread_rng.gen_range(0..SUBEFFECT_COUNT);
+        while self.last_subeffect == Some(subeffect) {
+            subeffect = self.thread_rng.gen_range(0..SUBEFFECT_COUNT);
+        }
+        self.last_subeffect = Some(subeffect);
+
+        let timeline = &mut self.timeline;
+
+        match subeffect {
+            0 => {
+                for _i in 0..3 {
+                    for color in XMAS_COLORS {
+                        timeline.push_set(ZoneColors([color; 4]), Duration::from_millis(500));
                     }
                 }
-                1 => {
-                    let color_1_index = thread_rng.gen_range(0..4);
-                    let used_colors_1: [u8; 3] = xmas_color_array[color_1_index];
+            }
+            1 => {
+                let color_1_index = self.thread_rng.gen_range(0..4);
+                let used_color_1 = XMAS_COLORS[color_1_index];
 
-                    let mut color_2_index = thread_rng.gen_range(0..4);
-                    while color_1_index == color_2_index {
-                        color_2_index = thread_rng.gen_range(0..4);
-                    }
-                    let used_colors_2: [u8; 3] = xmas_color_array[color_2_index];
+                let mut color_2_index = self.thread_rng.gen_range(0..4);
+                while color_1_index == color_2_index {
+                    color_2_index = self.thread_rng.gen_range(0..4);
+                }
+                let used_color_2 = XMAS_COLORS[color_2_index];
 
-                    for _i in 0..4 {
-                        manager.keyboard.solid_set_colors_to(used_colors_1).unwrap();
-                        thread::sleep(Duration::from_millis(400));
-                        manager.keyboard.solid_set_colors_to(used_colors_2).unwrap();
-                        thread::sleep(Duration::from_millis(400));
-                    }
+                for _i in 0..4 {
+                    timeline.push_set(ZoneColors([used_color_1; 4]), Duration::from_millis(400));
+                    timeline.push_set(ZoneColors([used_color_2; 4]), Duration::from_millis(400));
                 }
-                2 => {
-                    let steps = 100;
-                    manager.keyboard.transition_colors_to(&[0; 12], steps, 1).unwrap();
-                    let mut used_colors_array: [u8; 12] = [0; 12];
-                    let left_or_right = thread_rng.gen_range(0..2);
-                    if left_or_right == 0 {
-                        for color in xmas_color_array {
-                            for j in (0..12).step_by(3) {
-                                used_colors_array[j] = color[0];
-                                used_colors_array[j + 1] = color[1];
-                                used_colors_array[j + 2] = color[2];
-                                manager.keyboard.transition_colors_to(&used_colors_array, steps, 1).unwrap();
-                            }
-                            for j in (0..12).step_by(3) {
-                                used_colors_array[j] = 0;
-                                used_colors_array[j + 1] = 0;
-                                used_colors_array[j + 2] = 0;
-                                manager.keyboard.transition_colors_to(&used_colors_array, steps, 1).unwrap();
-                            }
-                        }
-                    } else {
-                        for i in 0..4 {
-                            for j in (0..12).step_by(3) {
-                                used_colors_array[11 - j] = xmas_color_array[3 - i][0];
-                                used_colors_array[11 - (j + 1)] = xmas_color_array[3 - i][1];
-                                used_colors_array[11 - (j + 2)] = xmas_color_array[3 - i][2];
-                                manager.keyboard.transition_colors_to(&used_colors_array, steps, 1).unwrap();
-                            }
-                            for j in (0..12).step_by(3) {
-                                used_colors_array[11 - j] = 0;
-                                used_colors_array[11 - (j + 1)] = 0;
-                                used_colors_array[11 - (j + 2)] = 0;
-                                manager.keyboard.transition_colors_to(&used_colors_array, steps, 1).unwrap();
-                            }
-                        }
+            }
+            2 => {
+                let step = render::linear(Duration::from_millis(100));
+                timeline.push(ZoneColors::default(), step, Duration::ZERO);
+                let mut used_colors = ZoneColors::default();
+
+                // Sweep each color across the keyboard, starting from either side
+                let (zones, colors) = if self.thread_rng.gen_range(0..2) == 0 {
+                    (ZoneId::ALL, XMAS_COLORS)
+                } else {
+                    let (mut zones, mut colors) = (ZoneId::ALL, XMAS_COLORS);
+                    zones.reverse();
+                    colors.reverse();
+                    (zones, colors)
+                };
+
+                for color in colors {
+                    for zone in zones {
+                        used_colors[zone] = color;
+                        timeline.push(used_colors, step, Duration::ZERO);
                     }
-                }
-                3 => {
-                    let state1 = [255, 255, 255, 0, 0, 0, 255, 255, 255, 0, 0, 0];
-                    let state2 = [0, 0, 0, 255, 255, 255, 0, 0, 0, 255, 255, 255];
-                    let steps = 30;
-                    for _i in 0..4 {
-                        manager.keyboard.transition_colors_to(&state1, steps, 1).unwrap();
-                        thread::sleep(Duration::from_millis(400));
-                        manager.keyboard.transition_colors_to(&state2, steps, 1).unwrap();
-                        thread::sleep(Duration::from_millis(400));
+                    for zone in zones {
+                        used_colors[zone] = Rgb::BLACK;
+                        timeline.push(used_colors, step, Duration::ZERO);
                     }
                 }
-                _ => unreachable!("Subeffect index for Christmas effect is out of range."),
             }
+            3 => {
+                let state1 = ZoneColors([Rgb::WHITE, Rgb::BLACK, Rgb::WHITE, Rgb::BLACK]);
+                let state2 = ZoneColors([Rgb::BLACK, Rgb::WHITE, Rgb::BLACK, Rgb::WHITE]);
+                let step = render::linear(Duration::from_millis(30));
+                for _i in 0..4 {
+                    timeline.push(state1, step, Duration::from_millis(400));
+                    timeline.push(state2, step, Duration::from_millis(400));
+                }
+            }
+            _ => unreachable!("Subeffect index for Christmas effect is out of range."),
+        }
+    }
+}
+
+impl Renderer for ChristmasRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        if self.timeline.is_finished(tick.elapsed) {
+            self.queue_subeffect();
         }
+
+        self.timeline.render(tick.elapsed)
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "Festive color patterns",
+    parameters: &[],
+};
+
+impl Effect for Christmas {
+    fn kind(&self) -> Effects {
+        Effects::Christmas
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, _profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(ChristmasRenderer {
+            timeline: Timeline::new(ZoneColors::default()),
+            last_subeffect: None,
+            thread_rng: thread_rng(),
+        }))
     }
 }
@@ -0,0 +1,173 @@
+use legion_rgb_driver::color::{Rgb, ZoneColors};
+
+use crate::{enums::BlendMode, profile::Profile};
+
+use super::{
+    registry,
+    render::{LayerFrame, Renderer, Tick},
+    EffectError,
+};
+
+struct CompositorLayer {
+    renderer: Box<dyn Renderer>,
+    blend_mode: BlendMode,
+    opacity: f32,
+    /// What this layer drew last, so that effects building on their previous frame don't see the others
+    previous: ZoneColors,
+}
+
+/// Draws a profile's effect with its layers on top
+pub(super) struct Compositor {
+    base: Box<dyn Renderer>,
+    base_previous: Option<ZoneColors>,
+    layers: Vec<CompositorLayer>,
+}
+
+impl Compositor {
+    /// Layers whose effect can only be played by the keyboard itself are left out, as is the whole stack if the base effect is one of them
+    ///
+    /// [`Profile::validate`] reports those, so that loaded profiles never end up here with them
+    pub fn new(profile: &Profile) -> Option<Self> {
+        let base = registry::find(profile.effect).layer_renderer(profile)?;
+
+        let layers = profile
+            .layers
+            .iter()
+            .filter_map(|layer| {
+                let renderer = registry::find(layer.effect).layer_renderer(&layer.to_profile(profile))?;
+
+                Some(CompositorLayer {
+                    renderer,
+                    blend_mode: layer.blend_mode,
+                    opacity: layer.opacity.clamp(0.0, 1.0),
+                    previous: ZoneColors::default(),
+                })
+            })
+            .collect();
+
+        Some(Self { base, base_previous: None, layers })
+    }
+}
+
+impl Renderer for Compositor {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        let base_tick = Tick {
+            // The base starts off from whatever the keyboard was showing, like it would on its own
+            previous: self.base_previous.unwrap_or(tick.previous),
+            ..*tick
+        };
+
+        let mut colors = self.base.render(&base_tick);
+        self.base_previous = Some(colors);
+
+        for layer in &mut self.layers {
+            let frame = layer.renderer.render_layer(&Tick { previous: layer.previous, ..*tick });
+            layer.previous = frame.colors;
+
+            colors = blend(&colors, &frame, layer.blend_mode, layer.opacity);
+        }
+
+        colors
+    }
+
+    fn tick_rate(&self) -> Option<u32> {
+        self.layers.iter().map(|layer| &layer.renderer).chain([&self.base]).filter_map(|renderer| renderer.tick_rate()).max()
+    }
+
+    fn take_error(&mut self) -> Option<EffectError> {
+        self.layers
+            .iter_mut()
+            .map(|layer| &mut layer.renderer)
+            .chain([&mut self.base])
+            .find_map(|renderer| renderer.take_error())
+    }
+}
+
+fn blend(below: &ZoneColors, frame: &LayerFrame, blend_mode: BlendMode, opacity: f32) -> ZoneColors {
+    ZoneColors(std::array::from_fn(|i| {
+        let below = <[u8; 3]>::from(below.0[i]).map(|c| f32::from(c) / 255.0);
+        let above = <[u8; 3]>::from(frame.colors.0[i]).map(|c| f32::from(c) / 255.0);
+        let alpha = frame.alpha[i].clamp(0.0, 1.0) * opacity;
+
+        let channels: [f32; 3] = std::array::from_fn(|c| {
+            let (b, a) = (below[c], above[c]);
+
+            let blended = match blend_mode {
+                BlendMode::Normal => a,
+                BlendMode::Add => (a + b).min(1.0),
+                BlendMode::Multiply => a * b,
+                BlendMode::Max => a.max(b),
+                BlendMode::Screen => 1.0 - (1.0 - a) * (1.0 - b),
+            };
+
+            b + (blended - b) * alpha
+        });
+
+        Rgb::from(channels.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(color: Rgb, alpha: f32) -> LayerFrame {
+        LayerFrame {
+            colors: ZoneColors([color; 4]),
+            alpha: [alpha; 4],
+        }
+    }
+
+    fn blend_one(below: Rgb, above: Rgb, blend_mode: BlendMode, alpha: f32, opacity: f32) -> Rgb {
+        blend(&ZoneColors([below; 4]), &frame(above, alpha), blend_mode, opacity).0[0]
+    }
+
+    #[test]
+    fn blend_modes() {
+        let below = Rgb::new(100, 200, 0);
+        let above = Rgb::new(200, 100, 255);
+
+        assert_eq!(blend_one(below, above, BlendMode::Normal, 1.0, 1.0), above);
+        assert_eq!(blend_one(below, above, BlendMode::Add, 1.0, 1.0), Rgb::new(255, 255, 255));
+        assert_eq!(blend_one(below, above, BlendMode::Multiply, 1.0, 1.0), Rgb::new(78, 78, 0));
+        assert_eq!(blend_one(below, above, BlendMode::Max, 1.0, 1.0), Rgb::new(200, 200, 255));
+        assert_eq!(blend_one(below, above, BlendMode::Screen, 1.0, 1.0), Rgb::new(222, 222, 255));
+    }
+
+    #[test]
+    fn multiplying_by_white_keeps_the_colors_below() {
+        let below = Rgb::new(12, 34, 56);
+
+        assert_eq!(blend_one(below, Rgb::new(255, 255, 255), BlendMode::Multiply, 1.0, 1.0), below);
+    }
+
+    #[test]
+    fn see_through_layers_keep_the_colors_below() {
+        let below = Rgb::new(12, 34, 56);
+        let above = Rgb::new(255, 0, 255);
+
+        for blend_mode in [BlendMode::Normal, BlendMode::Add, BlendMode::Multiply, BlendMode::Max, BlendMode::Screen] {
+            assert_eq!(blend_one(below, above, blend_mode, 0.0, 1.0), below, "{blend_mode:?}");
+            assert_eq!(blend_one(below, above, blend_mode, 1.0, 0.0), below, "{blend_mode:?}");
+        }
+    }
+
+    #[test]
+    fn alpha_and_opacity_mix_towards_the_layer() {
+        let below = Rgb::new(0, 100, 200);
+        let above = Rgb::new(200, 100, 0);
+
+        assert_eq!(blend_one(below, above, BlendMode::Normal, 0.5, 1.0), Rgb::new(100, 100, 100));
+        assert_eq!(blend_one(below, above, BlendMode::Normal, 1.0, 0.5), Rgb::new(100, 100, 100));
+        assert_eq!(blend_one(below, above, BlendMode::Normal, 0.5, 0.5), Rgb::new(50, 100, 150));
+    }
+
+    #[test]
+    fn out_of_range_alpha_is_clamped() {
+        let below = Rgb::new(0, 0, 0);
+        let above = Rgb::new(200, 100, 0);
+
+        assert_eq!(blend_one(below, above, BlendMode::Normal, 2.0, 1.0), above);
+        assert_eq!(blend_one(below, above, BlendMode::Normal, -1.0, 1.0), below);
+    }
+}
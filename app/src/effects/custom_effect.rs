@@ -1,41 +1,283 @@
-use std::path::Path;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use error_stack::{Result, ResultExt};
+use error_stack::{Report, Result};
+use legion_rgb_driver::transition::{ColorSpace, Easing, Transition};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::util::StorageTrait;
+use crate::{
+    diagnostics::{self, Diagnostic},
+    enums::{Brightness, Direction, Effects},
+    profile::{self, Profile},
+};
+
+use super::{
+    keyframes::{KeyframeEffect, KeyframeRenderer},
+    script::Script,
+};
+
+/// One of the steps of a [`StepEffect`], told apart by its `step_type`
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(tag = "step_type")]
+pub enum EffectStep {
+    /// Swap the colors instantly
+    Set(ColorStep),
+    /// Smoothly go to the colors
+    Transition(ColorStep),
+    /// Play one of the app's effects for a while
+    Effect(EffectRun),
+    /// Play a saved profile for a while
+    Profile(ProfileRun),
+}
+
+impl EffectStep {
+    /// How long to wait before going to the next step
+    pub fn sleep(&self) -> Duration {
+        match self {
+            Self::Set(step) | Self::Transition(step) => Duration::from_millis(step.sleep),
+            Self::Effect(_) | Self::Profile(_) => Duration::ZERO,
+        }
+    }
+}
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
-pub struct EffectStep {
+pub struct ColorStep {
     pub rgb_array: [u8; 12],
-    pub step_type: EffectType,
     pub brightness: u8,
     pub steps: u8,
     pub delay_between_steps: u64,
     pub sleep: u64,
+    /// Setting either of these makes a transition last `steps * delay_between_steps` ms regardless of how long each step takes to send
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub easing: Option<Easing>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color_space: Option<ColorSpace>,
+}
+
+impl ColorStep {
+    /// The timed transition this step opted into, if any
+    pub fn transition(&self) -> Option<Transition> {
+        if self.easing.is_none() && self.color_space.is_none() {
+            return None;
+        }
+
+        let duration = Duration::from_millis(u64::from(self.steps) * self.delay_between_steps);
+
+        Some(Transition::new(duration, self.easing.unwrap_or_default(), self.color_space.unwrap_or_default()))
+    }
+}
+
+/// The settings of an effect, like a profile would hold them
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct EffectRun {
+    pub effect: Effects,
+    #[serde(default)]
+    pub rgb_array: [u8; 12],
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default = "default_speed")]
+    pub speed: u8,
+    #[serde(default = "default_brightness")]
+    pub brightness: u8,
+    /// How long to play the effect for (In ms)
+    pub duration: u64,
+}
+
+fn default_speed() -> u8 {
+    1
+}
+
+fn default_brightness() -> u8 {
+    1
+}
+
+impl EffectRun {
+    pub fn to_profile(&self) -> Profile {
+        Profile {
+            rgb_zones: profile::arr_to_zones(self.rgb_array),
+            effect: self.effect,
+            direction: self.direction,
+            speed: self.speed,
+            brightness: if self.brightness > 1 { Brightness::High } else { Brightness::Low },
+            ..Profile::default()
+        }
+    }
 }
 
 #[derive(Clone, Deserialize, Serialize, Debug)]
-pub enum EffectType {
-    Set,
-    Transition,
+pub struct ProfileRun {
+    /// Relative to the effect's file
+    pub path: PathBuf,
+    /// How long to play the profile for (In ms)
+    pub duration: u64,
+    /// Read along with the effect so that a missing profile is caught right away
+    ///
+    /// Step effects only come from [`CustomEffect::from_file`], so this is always set by the time they are played
+    #[serde(skip)]
+    pub profile: Option<Profile>,
 }
 
+/// A list of colors to go through that every zone follows together, the first version of the JSON format
 #[derive(Deserialize, Serialize, Debug)]
-pub struct CustomEffect {
+pub struct StepEffect {
     pub effect_steps: Vec<EffectStep>,
     pub should_loop: bool,
 }
 
+impl StepEffect {
+    /// The values that are readable but the keyboard can't play
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut problems = Vec::new();
+
+        if self.effect_steps.is_empty() {
+            problems.push(Diagnostic::at_field("effect_steps", "there are no steps to play"));
+        }
+
+        for (i, step) in self.effect_steps.iter().enumerate() {
+            let brightness = match step {
+                EffectStep::Set(step) | EffectStep::Transition(step) => Some(step.brightness),
+                EffectStep::Effect(run) => Some(run.brightness),
+                EffectStep::Profile(_) => None,
+            };
+
+            if let Some(brightness) = brightness.filter(|brightness| !(1..=2).contains(brightness)) {
+                problems.push(Diagnostic::at_field(format!("effect_steps[{i}].brightness"), format!("must be 1 (low) or 2 (high), got {brightness}")));
+            }
+
+            match step {
+                EffectStep::Transition(step) if step.steps == 0 => {
+                    problems.push(Diagnostic::at_field(format!("effect_steps[{i}].steps"), "a transition needs at least 1 step"));
+                }
+                EffectStep::Effect(run) => {
+                    problems.extend(run.to_profile().validate().into_iter().map(|diagnostic| diagnostic.nested(&format!("effect_steps[{i}]"))));
+                }
+                _ => {}
+            }
+        }
+
+        problems
+    }
+
+    /// Read the profiles the steps play, their paths being relative to `directory`
+    fn load_profiles(&mut self, directory: &Path) -> Vec<Diagnostic> {
+        let mut problems = Vec::new();
+
+        for (i, step) in self.effect_steps.iter_mut().enumerate() {
+            if let EffectStep::Profile(run) = step {
+                match Profile::load_profile(&directory.join(&run.path)) {
+                    Ok(profile) => run.profile = Some(profile),
+                    Err(err) => problems.push(Diagnostic::at_field(
+                        format!("effect_steps[{i}].path"),
+                        format!("could not load \"{}\": {}", run.path.display(), diagnostics::summary(&err)),
+                    )),
+                }
+            }
+        }
+
+        problems
+    }
+}
+
+/// An effect made by the user
+#[derive(Debug)]
+pub enum CustomEffect {
+    Steps(StepEffect),
+    /// Checked and laid out when it was loaded
+    Keyframes(KeyframeRenderer),
+    Script(Script),
+}
+
 #[derive(Debug, Error)]
 #[error("Could not load custom effect")]
 pub struct LoadCustomEffectError;
 
+/// Files without a `version` are from before it was added
+#[derive(Deserialize)]
+struct Version {
+    #[serde(default = "first_version")]
+    version: u32,
+}
+
+fn first_version() -> u32 {
+    1
+}
+
 impl CustomEffect {
+    /// Load a script from a `.rhai` file, or an effect in either version of the JSON format from anything else
+    ///
+    /// Errors carry a [`Diagnostic`] for every problem found
     pub fn from_file(path: &Path) -> Result<Self, LoadCustomEffectError> {
-        Self::load(path).change_context(LoadCustomEffectError)
+        let source = fs::read_to_string(path).map_err(|err| {
+            let diagnostic = Diagnostic::new(err.to_string()).in_file(path);
+            Report::new(err).change_context(LoadCustomEffectError).attach_printable(diagnostic)
+        })?;
+
+        let effect = if path.extension().is_some_and(|extension| extension == "rhai") {
+            Script::compile(&source).map(Self::Script).map_err(|diagnostic| vec![diagnostic])
+        } else {
+            Self::from_json(&source, path.parent().unwrap_or(Path::new("")))
+        };
+
+        effect.map_err(|problems| diagnostics::report(LoadCustomEffectError, problems.into_iter().map(|diagnostic| diagnostic.in_file(path)).collect()))
+    }
+
+    /// `directory` is where the paths in the effect are relative to
+    fn from_json(json: &str, directory: &Path) -> std::result::Result<Self, Vec<Diagnostic>> {
+        let json_error = |err: serde_json::Error| vec![Diagnostic::from_json_error(&err)];
+
+        let Version { version } = serde_json::from_str(json).map_err(json_error)?;
+
+        match version {
+            1 => {
+                let mut effect: StepEffect = serde_json::from_str(json).map_err(json_error)?;
+
+                let mut problems = effect.validate();
+                problems.extend(effect.load_profiles(directory));
+
+                if problems.is_empty() {
+                    Ok(Self::Steps(effect))
+                } else {
+                    Err(problems)
+                }
+            }
+            2 => {
+                let effect: KeyframeEffect = serde_json::from_str(json).map_err(json_error)?;
+
+                effect.compile().map(Self::Keyframes)
+            }
+            version => Err(vec![Diagnostic::at_field("version", format!("unsupported version {version}, expected 1 or 2"))]),
+        }
     }
 }
 
-impl<'a> StorageTrait<'a> for CustomEffect {}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn missing_profiles_are_reported_when_loading() {
+        let json = r#"{
+            "effect_steps": [{ "step_type": "Profile", "path": "does-not-exist.json", "duration": 1000 }],
+            "should_loop": false
+        }"#;
+
+        let fields: Vec<Option<String>> = CustomEffect::from_json(json, Path::new("")).unwrap_err().into_iter().map(|diagnostic| diagnostic.field).collect();
+
+        assert_eq!(fields, [Some("effect_steps[0].path".to_string())]);
+    }
+
+    #[test]
+    fn unknown_versions_are_rejected() {
+        let fields: Vec<Option<String>> = CustomEffect::from_json(r#"{ "version": 3 }"#, Path::new(""))
+            .unwrap_err()
+            .into_iter()
+            .map(|diagnostic| diagnostic.field)
+            .collect();
+
+        assert_eq!(fields, [Some("version".to_string())]);
+    }
+}
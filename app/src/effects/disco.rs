@@ -1,21 +1,67 @@
-use std::{sync::atomic::Ordering, thread, time::Duration};
+use std::time::Duration;
 
-use rand::Rng;
+use legion_rgb_driver::color::{Rgb, ZoneColors, ZoneId};
+use rand::{rngs::ThreadRng, thread_rng, Rng};
 
-use crate::profile::Profile;
+use crate::{enums::Effects, profile::Profile};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter, SOFTWARE_SPEED_RANGE},
+    render::{Renderer, Tick},
+};
+
+const COLORS: [Rgb; 6] = [
+    Rgb::new(255, 0, 0),
+    Rgb::new(255, 255, 0),
+    Rgb::new(0, 255, 0),
+    Rgb::new(0, 255, 255),
+    Rgb::new(0, 0, 255),
+    Rgb::new(255, 0, 255),
+];
 
 pub(super) struct Disco;
 
-impl Disco {
-    pub fn play(manager: &mut super::Inner, p: &Profile, thread_rng: &mut rand::rngs::ThreadRng) {
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            let colors = [[255, 0, 0], [255, 255, 0], [0, 255, 0], [0, 255, 255], [0, 0, 255], [255, 0, 255]];
-            let colors_index = thread_rng.gen_range(0..6);
-            let new_values = colors[colors_index];
+struct DiscoRenderer {
+    interval: Duration,
+    next_change: Duration,
+    thread_rng: ThreadRng,
+}
+
+impl Renderer for DiscoRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        let mut colors = tick.previous;
 
-            let zone_index = thread_rng.gen_range(0..4);
-            manager.keyboard.set_zone_by_index(zone_index, new_values).unwrap();
-            thread::sleep(Duration::from_millis(2000 / (u64::from(p.speed) * 4)));
+        // Switch a random zone to a random color every interval, catching up if the ticks are further apart
+        while tick.elapsed >= self.next_change {
+            let zone = ZoneId::ALL[self.thread_rng.gen_range(0..4)];
+            colors[zone] = COLORS[self.thread_rng.gen_range(0..COLORS.len())];
+
+            self.next_change += self.interval;
         }
+
+        colors
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "Zones randomly switching between bright colors",
+    parameters: &[Parameter::Speed(SOFTWARE_SPEED_RANGE)],
+};
+
+impl Effect for Disco {
+    fn kind(&self) -> Effects {
+        Effects::Disco
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(DiscoRenderer {
+            interval: Duration::from_millis(2000 / (u64::from(profile.speed) * 4)),
+            next_change: Duration::ZERO,
+            thread_rng: thread_rng(),
+        }))
     }
 }
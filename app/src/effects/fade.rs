@@ -1,58 +1,66 @@
-use std::{
-    sync::{
-        atomic::{AtomicBool, Ordering},
-        Arc,
-    },
-    thread,
-    time::{Duration, Instant},
+use std::time::Duration;
+
+use device_query::{DeviceQuery, DeviceState};
+use legion_rgb_driver::{
+    color::ZoneColors,
+    transition::{ColorSpace, Easing, Transition},
 };
 
-use device_query::DeviceQuery;
+use crate::{enums::Effects, profile::Profile};
 
-use crate::profile::Profile;
+use super::{
+    registry::{Effect, EffectInfo, Parameter, SOFTWARE_SPEED_RANGE},
+    render::{Renderer, Tick},
+};
 
 pub(super) struct Fade;
 
-impl Fade {
-    pub fn play(manager: &mut super::Inner, p: &Profile) {
-        let stop_signals = manager.stop_signals.clone();
-
-        let kill_thread = Arc::new(AtomicBool::new(false));
-        let exit_thread = kill_thread.clone();
-
-        let state = device_query::DeviceState::new();
-
-        thread::spawn(move || {
-            let state = device_query::DeviceState::new();
-
-            loop {
-                if !state.get_keys().is_empty() {
-                    stop_signals.keyboard_stop_signal.store(true, Ordering::SeqCst);
-                }
-
-                if exit_thread.load(Ordering::SeqCst) {
-                    break;
-                }
-
-                thread::sleep(Duration::from_millis(5));
-            }
-        });
-
-        let mut now = Instant::now();
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            if state.get_keys().is_empty() {
-                if now.elapsed() > Duration::from_secs(20 / u64::from(p.speed)) {
-                    manager.keyboard.transition_colors_to(&[0; 12], 230, 3).unwrap();
-                } else {
-                    thread::sleep(Duration::from_millis(20));
-                }
-            } else {
-                manager.keyboard.set_colors_to(&p.rgb_array()).unwrap();
-                manager.stop_signals.keyboard_stop_signal.store(false, Ordering::SeqCst);
-                now = Instant::now();
-            }
+struct FadeRenderer {
+    colors: ZoneColors,
+    timeout: Duration,
+    fade_out: Transition,
+    state: DeviceState,
+    last_key_press: Duration,
+}
+
+impl Renderer for FadeRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        if !self.state.get_keys().is_empty() {
+            self.last_key_press = tick.elapsed;
         }
 
-        kill_thread.store(true, Ordering::SeqCst);
+        let idle = tick.elapsed - self.last_key_press;
+
+        if idle > self.timeout {
+            self.fade_out.colors_at(&self.colors, &ZoneColors::default(), idle - self.timeout)
+        } else {
+            self.colors
+        }
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "The zone colors fading out after a while without typing",
+    parameters: &[Parameter::Colors, Parameter::Speed(SOFTWARE_SPEED_RANGE)],
+};
+
+impl Effect for Fade {
+    fn kind(&self) -> Effects {
+        Effects::Fade
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(FadeRenderer {
+            colors: profile.zone_colors(),
+            timeout: Duration::from_secs(20 / u64::from(profile.speed)),
+            // Dimming in linear light with a slow tail looks closer to a real fade out than a straight sRGB ramp
+            fade_out: Transition::new(Duration::from_millis(690), Easing::EaseOut, ColorSpace::LinearRgb),
+            state: DeviceState::new(),
+            last_key_press: Duration::ZERO,
+        }))
     }
 }
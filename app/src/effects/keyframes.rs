@@ -0,0 +1,517 @@
+use std::time::Duration;
+
+use legion_rgb_driver::{
+    color::{Rgb, ZoneColors},
+    transition::{ColorSpace, Easing, Transition},
+    ZONE_RANGE,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::diagnostics::Diagnostic;
+
+use super::render::{Renderer, Tick};
+
+/// Repeat blocks can nest, don't let a couple of large counts eat all the memory
+const MAX_SEGMENTS_PER_TRACK: usize = 10_000;
+
+/// A custom effect in the second format, where each zone can follow its own keyframes
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct KeyframeEffect {
+    pub version: u32,
+    /// The hardware brightness to play the effect at, the current one is kept if left out
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u8>,
+    #[serde(default, rename = "loop")]
+    pub loop_count: LoopCount,
+    pub tracks: Vec<Track>,
+}
+
+/// How many times the effect is played
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "LoopCountRepr", into = "LoopCountRepr")]
+pub enum LoopCount {
+    #[default]
+    Once,
+    Times(u32),
+    Infinite,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum LoopCountRepr {
+    Times(u32),
+    Word(String),
+}
+
+impl TryFrom<LoopCountRepr> for LoopCount {
+    type Error = String;
+
+    fn try_from(repr: LoopCountRepr) -> Result<Self, Self::Error> {
+        match repr {
+            LoopCountRepr::Times(0) => Err("the loop count must be at least 1".to_string()),
+            LoopCountRepr::Times(1) => Ok(Self::Once),
+            LoopCountRepr::Times(times) => Ok(Self::Times(times)),
+            LoopCountRepr::Word(word) if word.eq_ignore_ascii_case("infinite") => Ok(Self::Infinite),
+            LoopCountRepr::Word(word) => Err(format!("expected a number or \"infinite\" for the loop count, got \"{word}\"")),
+        }
+    }
+}
+
+impl From<LoopCount> for LoopCountRepr {
+    fn from(count: LoopCount) -> Self {
+        match count {
+            LoopCount::Once => Self::Times(1),
+            LoopCount::Times(times) => Self::Times(times),
+            LoopCount::Infinite => Self::Word("infinite".to_string()),
+        }
+    }
+}
+
+/// The keyframes followed by some of the zones
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Track {
+    /// From 0 (left) to 3 (right), all of them if left out
+    #[serde(default = "all_zones")]
+    pub zones: Vec<u8>,
+    pub keyframes: Vec<TrackItem>,
+}
+
+fn all_zones() -> Vec<u8> {
+    ZONE_RANGE.collect()
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+#[serde(untagged)]
+pub enum TrackItem {
+    /// Play the keyframes `repeat` times in a row
+    Repeat {
+        repeat: u32,
+        keyframes: Vec<TrackItem>,
+    },
+    Keyframe(Keyframe),
+}
+
+#[derive(Clone, Deserialize, Serialize, Debug)]
+pub struct Keyframe {
+    pub color: Color,
+    /// How long it takes to get to `color` from the previous keyframe (In ms)
+    #[serde(default)]
+    pub duration: u64,
+    /// How long to stay on `color` once reached (In ms)
+    #[serde(default)]
+    pub hold: u64,
+    #[serde(default)]
+    pub easing: Easing,
+    #[serde(default)]
+    pub color_space: ColorSpace,
+}
+
+/// A color written as `[r, g, b]`, a `#rrggbb`/`#rgb` hex string or a name like `"orange"`
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(try_from = "ColorRepr", into = "ColorRepr")]
+pub struct Color(pub Rgb);
+
+#[derive(Deserialize, Serialize)]
+#[serde(untagged)]
+enum ColorRepr {
+    Channels([u8; 3]),
+    Text(String),
+}
+
+impl TryFrom<ColorRepr> for Color {
+    type Error = String;
+
+    fn try_from(repr: ColorRepr) -> Result<Self, Self::Error> {
+        match repr {
+            ColorRepr::Channels(channels) => Ok(Self(Rgb::from(channels))),
+            ColorRepr::Text(text) => named_color(&text)
+                .or_else(|| Rgb::from_hex(&text).ok())
+                .map(Self)
+                .ok_or_else(|| format!("\"{text}\" is neither a color name nor in the #rrggbb or #rgb format")),
+        }
+    }
+}
+
+impl From<Color> for ColorRepr {
+    fn from(color: Color) -> Self {
+        Self::Text(color.0.to_hex())
+    }
+}
+
+fn named_color(name: &str) -> Option<Rgb> {
+    let channels = match name.to_ascii_lowercase().as_str() {
+        "black" | "off" => [0, 0, 0],
+        "white" => [255, 255, 255],
+        "gray" | "grey" => [128, 128, 128],
+        "red" => [255, 0, 0],
+        "green" => [0, 255, 0],
+        "blue" => [0, 0, 255],
+        "yellow" => [255, 255, 0],
+        "cyan" => [0, 255, 255],
+        "magenta" => [255, 0, 255],
+        "orange" => [255, 128, 0],
+        "purple" => [128, 0, 255],
+        "pink" => [255, 64, 160],
+        _ => return None,
+    };
+
+    Some(Rgb::from(channels))
+}
+
+impl KeyframeEffect {
+    /// Check the effect can be played and lay its tracks out zone by zone, reporting every problem found
+    ///
+    /// Done once when the effect is loaded, the renderer is then cloned every time it is played
+    pub(super) fn compile(&self) -> Result<KeyframeRenderer, Vec<Diagnostic>> {
+        let mut problems = Vec::new();
+
+        if let Some(brightness) = self.brightness {
+            if !(1..=2).contains(&brightness) {
+                problems.push(Diagnostic::at_field("brightness", format!("must be 1 (low) or 2 (high), got {brightness}")));
+            }
+        }
+
+        let mut zones: [Option<ZoneTrack>; 4] = Default::default();
+
+        for (i, track) in self.tracks.iter().enumerate() {
+            let mut segments = Vec::new();
+            if let Err(message) = flatten(&track.keyframes, &mut segments) {
+                problems.push(Diagnostic::at_field(format!("tracks[{i}].keyframes"), message));
+            }
+
+            let zone_track = ZoneTrack::new(segments);
+
+            for &zone in &track.zones {
+                match zones.get_mut(usize::from(zone)) {
+                    Some(slot) if slot.is_none() => *slot = Some(zone_track.clone()),
+                    Some(_) => problems.push(Diagnostic::at_field(format!("tracks[{i}].zones"), format!("zone {zone} is already used by another track"))),
+                    None => problems.push(Diagnostic::at_field(
+                        format!("tracks[{i}].zones"),
+                        format!("there is no zone {zone}, zones go from {} to {}", ZONE_RANGE.start(), ZONE_RANGE.end()),
+                    )),
+                }
+            }
+        }
+
+        let cycle = zones.iter().flatten().map(|track| track.duration).max().unwrap_or_default();
+
+        if cycle.is_zero() && self.loop_count == LoopCount::Infinite {
+            problems.push(Diagnostic::at_field("loop", "an effect that loops forever needs at least one keyframe with a duration or a hold"));
+        }
+
+        if !problems.is_empty() {
+            return Err(problems);
+        }
+
+        Ok(KeyframeRenderer {
+            brightness: self.brightness,
+            zones,
+            cycle,
+            loop_count: self.loop_count,
+            start: None,
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Segment {
+    to: Rgb,
+    transition: Transition,
+    hold: Duration,
+}
+
+fn flatten(items: &[TrackItem], segments: &mut Vec<Segment>) -> Result<(), String> {
+    for item in items {
+        match item {
+            TrackItem::Keyframe(keyframe) => {
+                if segments.len() >= MAX_SEGMENTS_PER_TRACK {
+                    return Err(format!("more than {MAX_SEGMENTS_PER_TRACK} keyframes once the repeats are expanded"));
+                }
+
+                segments.push(Segment {
+                    to: keyframe.color.0,
+                    transition: Transition::new(Duration::from_millis(keyframe.duration), keyframe.easing, keyframe.color_space),
+                    hold: Duration::from_millis(keyframe.hold),
+                });
+            }
+            TrackItem::Repeat { repeat, keyframes } => {
+                for _ in 0..*repeat {
+                    let before = segments.len();
+                    flatten(keyframes, segments)?;
+
+                    // Nothing to repeat
+                    if segments.len() == before {
+                        break;
+                    }
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Clone, Debug)]
+struct ZoneTrack {
+    segments: Vec<Segment>,
+    /// When each segment starts, relative to the start of the track
+    starts: Vec<Duration>,
+    duration: Duration,
+}
+
+impl ZoneTrack {
+    fn new(segments: Vec<Segment>) -> Self {
+        let mut starts = Vec::with_capacity(segments.len());
+        let mut duration = Duration::ZERO;
+
+        for segment in &segments {
+            starts.push(duration);
+            duration = duration.saturating_add(segment.transition.duration).saturating_add(segment.hold);
+        }
+
+        Self { segments, starts, duration }
+    }
+
+    fn last_color(&self) -> Option<Rgb> {
+        self.segments.last().map(|segment| segment.to)
+    }
+
+    /// The color `offset` into the track when it starts off from `from`, tracks that are done stay on their last color
+    fn color_at(&self, from: Rgb, offset: Duration) -> Rgb {
+        let current = self.starts.partition_point(|&start| start <= offset);
+
+        let Some(index) = current.checked_sub(1) else {
+            return from;
+        };
+
+        let segment = &self.segments[index];
+        let from = index.checked_sub(1).map_or(from, |previous| self.segments[previous].to);
+        let elapsed = offset - self.starts[index];
+
+        let progress = if segment.transition.duration.is_zero() {
+            1.0
+        } else {
+            elapsed.as_secs_f32() / segment.transition.duration.as_secs_f32()
+        };
+
+        segment.transition.color_space.interpolate(from, segment.to, segment.transition.easing.apply(progress))
+    }
+}
+
+/// Plays a [`KeyframeEffect`], every zone loops together once the longest track is done
+#[derive(Clone, Debug)]
+pub struct KeyframeRenderer {
+    /// The hardware brightness to play the effect at, the current one is kept if left out
+    pub brightness: Option<u8>,
+    zones: [Option<ZoneTrack>; 4],
+    cycle: Duration,
+    loop_count: LoopCount,
+    /// The colors the keyboard had when the effect started, kept by the zones without a track
+    start: Option<ZoneColors>,
+}
+
+impl KeyframeRenderer {
+    fn iterations(&self) -> Option<u32> {
+        match self.loop_count {
+            LoopCount::Once => Some(1),
+            LoopCount::Times(times) => Some(times),
+            LoopCount::Infinite => None,
+        }
+    }
+
+    fn is_done(&self, elapsed: Duration) -> bool {
+        self.iterations().is_some_and(|iterations| self.cycle.checked_mul(iterations).is_some_and(|end| elapsed >= end))
+    }
+}
+
+impl Renderer for KeyframeRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        let start = *self.start.get_or_insert(tick.previous);
+
+        let (first_pass, offset) = if self.is_done(tick.elapsed) {
+            // Stay on the very end of the last pass
+            (self.iterations() == Some(1), self.cycle)
+        } else if self.cycle.is_zero() {
+            (true, Duration::ZERO)
+        } else {
+            let cycle = self.cycle.as_nanos();
+            let elapsed = tick.elapsed.as_nanos();
+
+            (elapsed < cycle, Duration::from_nanos((elapsed % cycle) as u64))
+        };
+
+        ZoneColors(std::array::from_fn(|i| match &self.zones[i] {
+            Some(track) => {
+                // Later passes pick up from where the previous one ended
+                let from = if first_pass { start.0[i] } else { track.last_color().unwrap_or(start.0[i]) };
+
+                track.color_at(from, offset)
+            }
+            None => start.0[i],
+        }))
+    }
+
+    fn is_finished(&self, tick: &Tick) -> bool {
+        self.is_done(tick.elapsed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const RED: Rgb = Rgb::new(255, 0, 0);
+    const BLUE: Rgb = Rgb::new(0, 0, 255);
+
+    fn keyframe(color: Rgb, duration: u64, hold: u64) -> TrackItem {
+        TrackItem::Keyframe(Keyframe {
+            color: Color(color),
+            duration,
+            hold,
+            easing: Easing::Linear,
+            color_space: ColorSpace::Srgb,
+        })
+    }
+
+    fn flattened(items: &[TrackItem]) -> Result<Vec<Segment>, String> {
+        let mut segments = Vec::new();
+        flatten(items, &mut segments).map(|()| segments)
+    }
+
+    fn track(items: &[TrackItem]) -> ZoneTrack {
+        ZoneTrack::new(flattened(items).unwrap())
+    }
+
+    #[test]
+    fn repeats_are_expanded() {
+        let items = [
+            keyframe(RED, 0, 0),
+            TrackItem::Repeat {
+                repeat: 2,
+                keyframes: vec![
+                    keyframe(BLUE, 0, 0),
+                    TrackItem::Repeat {
+                        repeat: 3,
+                        keyframes: vec![keyframe(RED, 0, 0)],
+                    },
+                ],
+            },
+        ];
+
+        let colors: Vec<Rgb> = flattened(&items).unwrap().iter().map(|segment| segment.to).collect();
+
+        assert_eq!(colors, [RED, BLUE, RED, RED, RED, BLUE, RED, RED, RED]);
+    }
+
+    #[test]
+    fn empty_repeats_are_skipped() {
+        let items = [TrackItem::Repeat {
+            repeat: u32::MAX,
+            keyframes: Vec::new(),
+        }];
+
+        assert!(flattened(&items).unwrap().is_empty());
+    }
+
+    #[test]
+    fn tracks_are_capped() {
+        let at_the_cap = [TrackItem::Repeat {
+            repeat: MAX_SEGMENTS_PER_TRACK as u32,
+            keyframes: vec![keyframe(RED, 0, 0)],
+        }];
+        let over_the_cap = [TrackItem::Repeat {
+            repeat: 1000,
+            keyframes: vec![TrackItem::Repeat {
+                repeat: 1000,
+                keyframes: vec![keyframe(RED, 0, 0)],
+            }],
+        }];
+
+        assert_eq!(flattened(&at_the_cap).unwrap().len(), MAX_SEGMENTS_PER_TRACK);
+        assert!(flattened(&over_the_cap).is_err());
+    }
+
+    #[test]
+    fn colors_follow_the_keyframes() {
+        let track = track(&[keyframe(Rgb::new(200, 100, 0), 1000, 500), keyframe(BLUE, 0, 1000)]);
+        let at = |millis| track.color_at(Rgb::BLACK, Duration::from_millis(millis));
+
+        assert_eq!(track.duration, Duration::from_millis(2500));
+        assert_eq!(at(0), Rgb::BLACK);
+        assert_eq!(at(500), Rgb::new(100, 50, 0));
+        assert_eq!(at(1000), Rgb::new(200, 100, 0));
+        assert_eq!(at(1499), Rgb::new(200, 100, 0));
+        // Keyframes without a duration are reached right away
+        assert_eq!(at(1500), BLUE);
+        assert_eq!(at(10_000), BLUE);
+    }
+
+    #[test]
+    fn loop_counts_are_parsed() {
+        let parse = |json| serde_json::from_str::<LoopCount>(json);
+
+        assert_eq!(parse("1").unwrap(), LoopCount::Once);
+        assert_eq!(parse("3").unwrap(), LoopCount::Times(3));
+        assert_eq!(parse(r#""infinite""#).unwrap(), LoopCount::Infinite);
+        assert_eq!(parse(r#""Infinite""#).unwrap(), LoopCount::Infinite);
+        assert!(parse("0").is_err());
+        assert!(parse(r#""forever""#).is_err());
+    }
+
+    #[test]
+    fn loop_counts_round_trip() {
+        for count in [LoopCount::Once, LoopCount::Times(5), LoopCount::Infinite] {
+            let json = serde_json::to_string(&count).unwrap();
+
+            assert_eq!(serde_json::from_str::<LoopCount>(&json).unwrap(), count);
+        }
+    }
+
+    #[test]
+    fn effects_stop_after_their_loops() {
+        let effect = KeyframeEffect {
+            version: 2,
+            brightness: None,
+            loop_count: LoopCount::Times(2),
+            tracks: vec![Track {
+                zones: all_zones(),
+                keyframes: vec![keyframe(RED, 0, 100), keyframe(BLUE, 0, 100)],
+            }],
+        };
+        let mut renderer = effect.compile().unwrap();
+        let tick = |millis| Tick {
+            elapsed: Duration::from_millis(millis),
+            ..Tick::default()
+        };
+
+        assert_eq!(renderer.render(&tick(0)), ZoneColors([RED; 4]));
+        assert_eq!(renderer.render(&tick(150)), ZoneColors([BLUE; 4]));
+        assert_eq!(renderer.render(&tick(250)), ZoneColors([RED; 4]));
+        assert!(!renderer.is_finished(&tick(399)));
+        assert!(renderer.is_finished(&tick(400)));
+        assert_eq!(renderer.render(&tick(1000)), ZoneColors([BLUE; 4]));
+    }
+
+    #[test]
+    fn overlapping_tracks_are_rejected() {
+        let effect = KeyframeEffect {
+            version: 2,
+            brightness: Some(3),
+            loop_count: LoopCount::Infinite,
+            tracks: vec![
+                Track {
+                    zones: vec![0, 1],
+                    keyframes: Vec::new(),
+                },
+                Track {
+                    zones: vec![1, 4],
+                    keyframes: Vec::new(),
+                },
+            ],
+        };
+
+        let fields: Vec<Option<String>> = effect.compile().unwrap_err().into_iter().map(|diagnostic| diagnostic.field).collect();
+
+        assert_eq!(fields, ["brightness", "tracks[1].zones", "tracks[1].zones", "loop"].map(|field| Some(field.to_string())));
+    }
+}
@@ -1,33 +1,65 @@
-use std::{sync::atomic::Ordering, thread, time::Duration};
+use std::time::Duration;
 
-use rand::{rngs::ThreadRng, Rng};
+use legion_rgb_driver::color::{ZoneColors, ZoneId};
+use rand::{rngs::ThreadRng, thread_rng, Rng};
 
-use crate::profile::Profile;
+use crate::{enums::Effects, profile::Profile};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter, SOFTWARE_SPEED_RANGE},
+    render::{self, Renderer, Tick, Timeline},
+};
 
 pub(super) struct Lightning;
 
-impl Lightning {
-    pub fn play(manager: &mut super::Inner, p: &Profile, thread_rng: &mut ThreadRng) {
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            let profile_array = p.rgb_array();
+struct LightningRenderer {
+    colors: ZoneColors,
+    speed: u8,
+    timeline: Timeline,
+    thread_rng: ThreadRng,
+}
 
-            if manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-                break;
-            }
-            let zone_index = thread_rng.gen_range(0..4);
-            let steps = thread_rng.gen_range(50..=200);
+impl Renderer for LightningRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        if self.timeline.is_finished(tick.elapsed) {
+            let zone = ZoneId::ALL[self.thread_rng.gen_range(0..4)];
+            let steps: u8 = self.thread_rng.gen_range(50..=200);
 
-            let mut arr = [0; 12];
-            let zone_start = zone_index * 3;
+            let mut flash = ZoneColors::default();
+            flash[zone] = self.colors[zone];
 
-            arr[zone_start] = profile_array[zone_start];
-            arr[zone_start + 1] = profile_array[zone_start + 1];
-            arr[zone_start + 2] = profile_array[zone_start + 2];
+            // Flash a zone then fade it out, in 5ms steps
+            let fade_out = render::linear(Duration::from_millis(u64::from(steps / self.speed) * 5));
+            let sleep_time = Duration::from_millis(self.thread_rng.gen_range(100..=2000));
 
-            manager.keyboard.set_colors_to(&arr).unwrap();
-            manager.keyboard.transition_colors_to(&[0; 12], steps / p.speed, 5).unwrap();
-            let sleep_time = thread_rng.gen_range(100..=2000);
-            thread::sleep(Duration::from_millis(sleep_time));
+            self.timeline.push_set(flash, Duration::ZERO);
+            self.timeline.push(ZoneColors::default(), fade_out, sleep_time);
         }
+
+        self.timeline.render(tick.elapsed)
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "Random zones flashing and fading out",
+    parameters: &[Parameter::Colors, Parameter::Speed(SOFTWARE_SPEED_RANGE)],
+};
+
+impl Effect for Lightning {
+    fn kind(&self) -> Effects {
+        Effects::Lightning
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(LightningRenderer {
+            colors: profile.zone_colors(),
+            speed: profile.speed,
+            timeline: Timeline::new(ZoneColors::default()),
+            thread_rng: thread_rng(),
+        }))
     }
 }
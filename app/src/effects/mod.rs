@@ -1,42 +1,63 @@
 use crate::{
-    enums::{Direction, Effects, Message},
-    profile::{self, Profile},
+    enums::{Effects, ExitBehavior, Message},
+    profile::Profile,
 };
 
-use crossbeam_channel::{Receiver, Sender};
+use crossbeam_channel::{Receiver, Sender, TryIter};
 use error_stack::{Result, ResultExt};
-use legion_rgb_driver::{BaseEffects, Keyboard, SPEED_RANGE};
-use rand::thread_rng;
+use legion_rgb_driver::{
+    capture::PayloadRecorder,
+    color::ZoneColors,
+    device::DeviceSelector,
+    error::{Error as DriverError, Result as DriverResult},
+    BaseEffects, Keyboard, LightingState, RetryPolicy, SPEED_RANGE,
+};
 use single_instance::SingleInstance;
 use std::{
+    env,
+    path::Path,
     sync::atomic::{AtomicBool, Ordering},
     thread,
-    time::Duration,
+    time::{Duration, Instant},
+};
+use std::{
+    sync::{Arc, Mutex},
+    thread::JoinHandle,
 };
-use std::{sync::Arc, thread::JoinHandle};
 use thiserror::Error;
 
 use self::{
-    ambient::AmbientLight,
-    christmas::Christmas,
-    custom_effect::{CustomEffect, EffectType},
-    disco::Disco,
-    fade::Fade,
-    lightning::Lightning,
-    ripple::Ripple,
-    swipe::Swipe,
-    temperature::Temperature,
+    compositor::Compositor,
+    custom_effect::{CustomEffect, EffectStep, StepEffect},
+    registry::Parameter,
+    render::{Renderer, Tick, TimeLimit},
 };
 
 mod ambient;
+mod built_in;
 mod christmas;
+mod compositor;
 pub mod custom_effect;
 mod disco;
 mod fade;
+mod keyframes;
 mod lightning;
+pub mod registry;
+mod render;
 mod ripple;
+mod script;
 mod swipe;
 mod temperature;
+pub mod visualizer;
+
+/// How often to look for the keyboard again after it has been disconnected
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Writes can fail sporadically (e.g. while the controller is busy), give them a few chances before bailing out
+const RETRY_POLICY: RetryPolicy = RetryPolicy {
+    attempts: 3,
+    backoff: Duration::from_millis(10),
+};
 
 #[derive(Debug, Error, PartialEq)]
 #[error("Could not create keyboard manager")]
@@ -47,19 +68,40 @@ pub enum ManagerCreationError {
     InstanceAlreadyRunning,
 }
 
+/// Something that went wrong while playing an effect, see [`EffectManager::errors`]
+#[derive(Debug, Error)]
+pub enum EffectError {
+    #[error("Keyboard error: {0}")]
+    Keyboard(#[from] DriverError),
+    #[error("Script error: {0}")]
+    Script(String),
+    #[error("Audio error: {0}")]
+    Audio(String),
+}
+
 /// Manager wrapper
 pub struct EffectManager {
     pub tx: Sender<Message>,
     inner_handle: Option<JoinHandle<()>>,
     stop_signals: StopSignals,
+    hardware_state: Option<LightingState>,
+    exit_behavior: Arc<Mutex<ExitBehavior>>,
+    error_rx: Receiver<EffectError>,
 }
 
 /// Controls the keyboard lighting logic
 struct Inner {
     keyboard: Keyboard,
+    device: Option<DeviceSelector>,
     rx: Receiver<Message>,
+    error_tx: Sender<EffectError>,
     stop_signals: StopSignals,
     last_profile: Profile,
+    tick_rate: u32,
+    hardware_state: Option<LightingState>,
+    exit_behavior: Arc<Mutex<ExitBehavior>>,
+    /// Show what scripts print, only when there is a console to show it on
+    echo_script_output: bool,
     // Can't drop this else it stops "reserving" whatever underlying implementation identifier it uses
     #[allow(dead_code)]
     single_instance: SingleInstance,
@@ -72,7 +114,8 @@ pub enum OperationMode {
 }
 
 impl EffectManager {
-    pub fn new(operation_mode: OperationMode) -> Result<Self, ManagerCreationError> {
+    /// Create the manager, using the first supported keyboard unless a specific device is selected
+    pub fn new(operation_mode: OperationMode, device: Option<&DeviceSelector>) -> Result<Self, ManagerCreationError> {
         let stop_signals = StopSignals {
             manager_stop_signal: Arc::new(AtomicBool::new(false)),
             keyboard_stop_signal: Arc::new(AtomicBool::new(false)),
@@ -85,38 +128,54 @@ impl EffectManager {
             return Err(ManagerCreationError::InstanceAlreadyRunning.into());
         }
 
-        let keyboard = legion_rgb_driver::get_keyboard(stop_signals.keyboard_stop_signal.clone())
+        let mut keyboard = open_keyboard(device, &stop_signals)
             .change_context(ManagerCreationError::AcquireKeyboard)
             .attach_printable("Ensure that you have a supported model and that the application has access to it.")
             .attach_printable("On Linux, see https://github.com/4JX/L5P-Keyboard-RGB#usage")?;
 
+        let hardware_state = keyboard.startup_state().cloned();
+
         let (tx, rx) = crossbeam_channel::unbounded::<Message>();
+        let (error_tx, error_rx) = crossbeam_channel::unbounded::<EffectError>();
+        let exit_behavior = Arc::new(Mutex::new(ExitBehavior::default()));
 
         let mut inner = Inner {
             keyboard,
+            device: device.cloned(),
             rx,
+            error_tx,
             stop_signals: stop_signals.clone(),
             last_profile: Profile::default(),
+            tick_rate: tick_rate_from_env(),
+            hardware_state: hardware_state.clone(),
+            exit_behavior: exit_behavior.clone(),
+            echo_script_output: matches!(operation_mode, OperationMode::Cli),
             single_instance,
         };
 
         macro_rules! effect_thread_loop {
             ($e: expr) => {
-                thread::spawn(move || loop {
-                    match $e {
-                        Some(message) => match message {
-                            Message::Profile { profile } => {
-                                inner.set_profile(profile);
-                            }
-                            Message::CustomEffect { effect } => {
-                                inner.custom_effect(&effect);
+                thread::spawn(move || {
+                    loop {
+                        match $e {
+                            Some(message) => match message {
+                                Message::Profile { profile } => {
+                                    let result = inner.set_profile(profile);
+                                    inner.recover(result, |inner| inner.set_profile(inner.last_profile.clone()));
+                                }
+                                Message::CustomEffect { effect } => {
+                                    let result = inner.custom_effect(&effect);
+                                    inner.recover(result, |inner| inner.custom_effect(&effect));
+                                }
+                                Message::Exit => break,
+                            },
+                            None => {
+                                thread::sleep(Duration::from_millis(20));
                             }
-                            Message::Exit => break,
-                        },
-                        None => {
-                            thread::sleep(Duration::from_millis(20));
                         }
                     }
+
+                    inner.exit();
                 })
             };
         }
@@ -130,11 +189,43 @@ impl EffectManager {
             tx,
             inner_handle: Some(inner_handle),
             stop_signals,
+            hardware_state,
+            exit_behavior,
+            error_rx,
         };
 
         Ok(manager)
     }
 
+    /// The lighting state the keyboard was showing before the manager took it over, only known for the models that can be read from
+    pub fn hardware_state(&self) -> Option<&LightingState> {
+        self.hardware_state.as_ref()
+    }
+
+    /// Set what the keyboard is left showing once the manager is dropped or the app is told to terminate
+    pub fn set_exit_behavior(&self, exit_behavior: ExitBehavior) {
+        *self.exit_behavior.lock().unwrap() = exit_behavior;
+    }
+
+    /// Interrupt the current effect and apply the exit behavior once `termination` fires (e.g. on Ctrl+C), so that the keyboard isn't left on whatever frame the effect was at
+    ///
+    /// The effect thread then finishes, which [`Self::join_and_exit`] waits for
+    pub fn stop_on(&self, termination: Receiver<()>) {
+        let (tx, stop_signals) = (self.tx.clone(), self.stop_signals.clone());
+
+        thread::spawn(move || {
+            if termination.recv().is_ok() {
+                stop_signals.store_true();
+                let _ = tx.send(Message::Exit);
+            }
+        });
+    }
+
+    /// Errors the effect thread ran into since the last call
+    pub fn errors(&self) -> TryIter<'_, EffectError> {
+        self.error_rx.try_iter()
+    }
+
     pub fn set_profile(&mut self, profile: Profile) {
         self.stop_signals.store_true();
         self.tx.try_send(Message::Profile { profile }).unwrap();
@@ -145,97 +236,315 @@ impl EffectManager {
         self.tx.send(Message::CustomEffect { effect }).unwrap();
     }
 
+    /// Wait for the effect thread to finish, printing any errors it runs into along the way
     pub fn join_and_exit(mut self) {
         self.tx.send(Message::Exit).unwrap();
         if let Some(handle) = self.inner_handle.take() {
+            while !handle.is_finished() {
+                for err in self.errors() {
+                    eprintln!("{err}");
+                }
+
+                thread::sleep(Duration::from_millis(50));
+            }
+
             handle.join().unwrap();
         };
+
+        for err in self.errors() {
+            eprintln!("{err}");
+        }
+    }
+
+    /// Interrupt the current effect and wait for the exit behavior to be applied
+    pub fn shutdown(&mut self) {
+        self.stop_signals.store_true();
+        let _ = self.tx.send(Message::Exit);
+
+        if let Some(handle) = self.inner_handle.take() {
+            let _ = handle.join();
+        }
     }
 }
 
+fn open_keyboard(device: Option<&DeviceSelector>, stop_signals: &StopSignals) -> DriverResult<Keyboard> {
+    let mut keyboard = match device {
+        Some(selector) => legion_rgb_driver::select_keyboard(selector, stop_signals.keyboard_stop_signal.clone()),
+        None => legion_rgb_driver::get_keyboard(stop_signals.keyboard_stop_signal.clone()),
+    }?;
+
+    keyboard.set_retry_policy(RETRY_POLICY);
+
+    if let Ok(capture_path) = env::var("LEGION_KEYBOARD_CAPTURE") {
+        keyboard.set_recorder(Some(PayloadRecorder::create(Path::new(&capture_path))?));
+    }
+
+    Ok(keyboard)
+}
+
+fn tick_rate_from_env() -> u32 {
+    env::var("LEGION_KEYBOARD_TICK_RATE")
+        .ok()
+        .and_then(|tick_rate| tick_rate.parse().ok())
+        .filter(|tick_rate| (1..=legion_rgb_driver::DEFAULT_MAX_FPS).contains(tick_rate))
+        .unwrap_or(render::DEFAULT_TICK_RATE)
+}
+
 impl Inner {
-    fn set_profile(&mut self, mut profile: Profile) {
+    /// Deal with the outcome of playing an effect, waiting for the keyboard to come back and running `retry` if it was disconnected midway
+    fn recover(&mut self, mut result: DriverResult<()>, mut retry: impl FnMut(&mut Self) -> DriverResult<()>) {
+        loop {
+            match result {
+                Ok(()) => return,
+                Err(DriverError::Disconnected) => {
+                    if !self.reconnect() {
+                        return;
+                    }
+
+                    result = retry(self);
+                }
+                // Keep the thread alive and let whoever is listening know
+                Err(err) => {
+                    let _ = self.error_tx.send(err.into());
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Poll for the keyboard until it can be opened again, giving up if something else was requested in the meantime
+    fn reconnect(&mut self) -> bool {
+        while !self.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
+            thread::sleep(RECONNECT_INTERVAL);
+
+            if let Ok(keyboard) = open_keyboard(self.device.as_ref(), &self.stop_signals) {
+                self.keyboard = keyboard;
+                return true;
+            }
+        }
+
+        false
+    }
+
+    fn set_profile(&mut self, profile: Profile) -> DriverResult<()> {
         self.last_profile = profile.clone();
 
         self.stop_signals.store_false();
-        let mut thread_rng = thread_rng();
 
-        self.keyboard.set_effect(BaseEffects::Static).unwrap();
-        if profile.effect.is_built_in() {
-            let clamped_speed = profile.speed.clamp(SPEED_RANGE.min().unwrap(), SPEED_RANGE.max().unwrap());
+        self.play_profile(&profile, None)?;
+
+        self.stop_signals.store_false();
 
-            self.keyboard.set_speed(clamped_speed).unwrap();
+        Ok(())
+    }
+
+    /// Play the profile's effect until told to stop, or for `limit` at most
+    fn play_profile(&mut self, profile: &Profile, limit: Option<Duration>) -> DriverResult<()> {
+        self.apply_base_state(profile)?;
+
+        let renderer = if profile.layers.is_empty() {
+            registry::find(profile.effect).renderer(profile)
+        } else {
+            Compositor::new(profile).map(|compositor| Box::new(compositor) as Box<dyn Renderer>)
         };
-        self.keyboard.set_brightness(profile.brightness as u8 + 1).unwrap();
 
-        match profile.effect {
-            Effects::Static => {
-                self.keyboard.set_colors_to(&profile.rgb_array()).unwrap();
-                self.keyboard.set_effect(BaseEffects::Static).unwrap();
+        match (renderer, limit) {
+            (Some(renderer), Some(limit)) => self.render_loop(Box::new(TimeLimit::new(renderer, limit))),
+            (Some(renderer), None) => self.render_loop(renderer),
+            // The keyboard plays it by itself
+            (None, Some(limit)) => {
+                self.wait(limit);
+                Ok(())
             }
-            Effects::Breath => {
-                self.keyboard.set_colors_to(&profile.rgb_array()).unwrap();
-                self.keyboard.set_effect(BaseEffects::Breath).unwrap();
+            (None, None) => Ok(()),
+        }
+    }
+
+    /// Sleep for `duration`, waking up early if something else was requested in the meantime
+    fn wait(&self, duration: Duration) {
+        let deadline = Instant::now() + duration;
+
+        while !self.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
+            let now = Instant::now();
+            if now >= deadline {
+                break;
             }
-            Effects::Smooth => {
-                self.keyboard.set_effect(BaseEffects::Smooth).unwrap();
+
+            thread::sleep((deadline - now).min(Duration::from_millis(50)));
+        }
+    }
+
+    /// Send the renderer's frames to the keyboard at a steady rate until told to stop
+    fn render_loop(&mut self, mut renderer: Box<dyn Renderer>) -> DriverResult<()> {
+        let interval = Duration::from_secs(1) / renderer.tick_rate().unwrap_or(self.tick_rate).max(1);
+
+        let start = Instant::now();
+        let mut next_tick = start;
+
+        while !self.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
+            let tick = Tick {
+                elapsed: start.elapsed(),
+                interval,
+                previous: self.keyboard.current_state().colors(),
+            };
+
+            let colors = renderer.render(&tick);
+            self.keyboard.set_colors(&colors)?;
+
+            if let Some(err) = renderer.take_error() {
+                let _ = self.error_tx.send(err);
             }
-            Effects::Wave => match profile.direction {
-                Direction::Left => {
-                    self.keyboard.set_effect(BaseEffects::LeftWave).unwrap();
-                }
-                Direction::Right => {
-                    self.keyboard.set_effect(BaseEffects::RightWave).unwrap();
-                }
-            },
 
-            Effects::Lightning => Lightning::play(self, &profile, &mut thread_rng),
-            Effects::AmbientLight { mut fps, mut saturation_boost } => {
-                fps = fps.clamp(1, 60);
-                saturation_boost = saturation_boost.clamp(0.0, 1.0);
+            if renderer.is_finished(&tick) {
+                break;
+            }
 
-                AmbientLight::play(self, fps, saturation_boost);
+            // Skip the ticks that were missed rather than rushing through them
+            next_tick += interval;
+            let now = Instant::now();
+            if next_tick > now {
+                thread::sleep(next_tick - now);
+            } else {
+                next_tick = now;
             }
-            Effects::SmoothWave => {
-                profile.rgb_zones = profile::arr_to_zones([255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255]);
-                Swipe::play(self, &profile);
+        }
+
+        Ok(())
+    }
+
+    /// Built-in effects are fully set up in a single write, the others start from a static state they then update themselves
+    fn apply_base_state(&mut self, profile: &Profile) -> DriverResult<()> {
+        let effect = registry::find(profile.effect);
+
+        let mut transaction = self
+            .keyboard
+            .transaction()
+            .effect(BaseEffects::Static)
+            .brightness(profile.brightness as u8 + 1)
+            .software_brightness(profile.software_brightness);
+
+        if let Some(hardware_effect) = effect.hardware_effect(profile.direction) {
+            let clamped_speed = profile.speed.clamp(SPEED_RANGE.min().unwrap(), SPEED_RANGE.max().unwrap());
+
+            transaction = transaction.effect(hardware_effect).speed(clamped_speed);
+
+            if effect.takes(&Parameter::Colors) {
+                transaction = transaction.colors(&profile.zone_colors());
             }
-            Effects::Swipe => Swipe::play(self, &profile),
-            Effects::Disco => Disco::play(self, &profile, &mut thread_rng),
-            Effects::Christmas => Christmas::play(self, &mut thread_rng),
-            Effects::Fade => Fade::play(self, &profile),
-            Effects::Temperature => Temperature::play(self),
-            Effects::Ripple => Ripple::play(self, &profile),
         }
-        self.stop_signals.store_false();
+
+        transaction.commit()
     }
 
-    fn custom_effect(&mut self, custom_effect: &CustomEffect) {
+    /// Leave the keyboard as the exit behavior says, there is no one left to report errors to at this point
+    fn exit(&mut self) {
+        let exit_behavior = self.exit_behavior.lock().unwrap().clone();
+
+        let _ = match exit_behavior {
+            ExitBehavior::Keep => Ok(()),
+            ExitBehavior::Restore => match self.hardware_state.clone() {
+                Some(state) => self
+                    .keyboard
+                    .transaction()
+                    .effect(state.effect_type())
+                    .speed(state.speed())
+                    .brightness(state.brightness())
+                    .colors(&state.colors())
+                    .software_brightness(100)
+                    .commit(),
+                None => Ok(()),
+            },
+            ExitBehavior::Profile(mut profile) => {
+                if !profile.effect.is_built_in() {
+                    profile.effect = Effects::Static;
+                }
+
+                self.apply_base_state(&profile)
+            }
+            ExitBehavior::Off => self.keyboard.transaction().effect(BaseEffects::Static).colors(&ZoneColors::default()).commit(),
+        };
+    }
+
+    fn custom_effect(&mut self, custom_effect: &CustomEffect) -> DriverResult<()> {
         self.stop_signals.store_false();
 
+        match custom_effect {
+            CustomEffect::Steps(step_effect) => self.step_effect(step_effect),
+            CustomEffect::Keyframes(renderer) => {
+                if let Some(brightness) = renderer.brightness {
+                    self.keyboard.set_brightness(brightness)?;
+                }
+                self.keyboard.set_effect(BaseEffects::Static)?;
+
+                // A fresh copy every time, so that playing it again starts over
+                self.render_loop(Box::new(renderer.clone()))
+            }
+            CustomEffect::Script(script) => {
+                self.keyboard.set_effect(BaseEffects::Static)?;
+
+                match script.renderer(self.echo_script_output) {
+                    Ok(renderer) => self.render_loop(Box::new(renderer)),
+                    // It did start when it was loaded, so this would be down to something outside of the script
+                    Err(err) => {
+                        let _ = self.error_tx.send(EffectError::Script(format!("Could not start the script: {err}")));
+                        Ok(())
+                    }
+                }
+            }
+        }
+    }
+
+    fn step_effect(&mut self, step_effect: &StepEffect) -> DriverResult<()> {
         'outer: loop {
-            for step in custom_effect.effect_steps.clone() {
-                self.keyboard.set_brightness(step.brightness).unwrap();
-                if let EffectType::Set = step.step_type {
-                    self.keyboard.set_colors_to(&step.rgb_array).unwrap();
-                } else {
-                    self.keyboard.transition_colors_to(&step.rgb_array, step.steps, step.delay_between_steps).unwrap();
+            // Colors only show while the keyboard is static, which effects played by previous steps may have changed
+            let mut played_effect = false;
+
+            for step in &step_effect.effect_steps {
+                if let EffectStep::Set(colors) | EffectStep::Transition(colors) = step {
+                    if played_effect {
+                        self.keyboard.set_effect(BaseEffects::Static)?;
+                        played_effect = false;
+                    }
+                    self.keyboard.set_brightness(colors.brightness)?;
+                }
+
+                match step {
+                    EffectStep::Set(colors) => self.keyboard.set_colors_to(&colors.rgb_array)?,
+                    EffectStep::Transition(colors) => {
+                        if let Some(transition) = colors.transition() {
+                            self.keyboard.transition_to(&ZoneColors::from_array(colors.rgb_array), &transition)?;
+                        } else {
+                            self.keyboard.transition_colors_to(&colors.rgb_array, colors.steps, colors.delay_between_steps)?;
+                        }
+                    }
+                    EffectStep::Effect(run) => {
+                        self.play_profile(&run.to_profile(), Some(Duration::from_millis(run.duration)))?;
+                        played_effect = true;
+                    }
+                    EffectStep::Profile(run) => {
+                        let profile = run.profile.as_ref().expect("Profiles should be read along with the effect");
+
+                        self.play_profile(profile, Some(Duration::from_millis(run.duration)))?;
+                        played_effect = true;
+                    }
                 }
                 if self.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
                     break 'outer;
                 }
-                thread::sleep(Duration::from_millis(step.sleep));
+                thread::sleep(step.sleep());
             }
-            if !custom_effect.should_loop {
+            if !step_effect.should_loop {
                 break;
             }
         }
+
+        Ok(())
     }
 }
 
 impl Drop for EffectManager {
     fn drop(&mut self) {
-        let _ = self.tx.send(Message::Exit);
+        self.shutdown();
     }
 }
 
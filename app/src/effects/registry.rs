@@ -0,0 +1,128 @@
+use std::ops::RangeInclusive;
+
+use legion_rgb_driver::BaseEffects;
+
+use crate::{
+    enums::{Direction, Effects},
+    profile::Profile,
+};
+
+use super::{
+    ambient::AmbientLight,
+    built_in::{Breath, Smooth, Static, Wave},
+    christmas::Christmas,
+    disco::Disco,
+    fade::Fade,
+    lightning::Lightning,
+    render::Renderer,
+    ripple::Ripple,
+    swipe::{SmoothWave, Swipe},
+    temperature::Temperature,
+    visualizer::Visualizer,
+};
+
+/// The speeds offered for the effects driven by the app, the keyboard's own ones use [`legion_rgb_driver::SPEED_RANGE`]
+pub const SOFTWARE_SPEED_RANGE: RangeInclusive<u8> = 1..=10;
+
+/// A profile option an effect makes use of
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Parameter {
+    /// The four zone colors
+    Colors,
+    Speed(RangeInclusive<u8>),
+    Direction,
+    /// Only taken by [`Effects::AmbientLight`], which stores it in the variant itself
+    Fps(RangeInclusive<u8>),
+    /// Only taken by [`Effects::AmbientLight`], which stores it in the variant itself
+    SaturationBoost,
+    /// Only taken by [`Effects::Visualizer`], which stores it in the variant itself
+    VisualizerMode,
+    /// Only taken by [`Effects::Visualizer`], which stores it in the variant itself
+    Sensitivity,
+    /// Only taken by [`Effects::Visualizer`], which stores it in the variant itself
+    Smoothing,
+    /// Only taken by [`Effects::Visualizer`], which stores it in the variant itself
+    Palette,
+}
+
+/// What the GUI and CLI need to know about an effect without running it
+#[derive(Debug)]
+pub struct EffectInfo {
+    pub description: &'static str,
+    pub parameters: &'static [Parameter],
+}
+
+/// An effect that can be selected in a profile
+///
+/// Adding an effect means adding its variant to [`Effects`], implementing this trait and listing it in [`EFFECTS`]
+pub(crate) trait Effect: Sync {
+    /// The effect as stored in profiles, with its default settings
+    fn kind(&self) -> Effects;
+
+    fn info(&self) -> &'static EffectInfo;
+
+    /// The effect the keyboard plays by itself, if this is one of them
+    fn hardware_effect(&self, _direction: Direction) -> Option<BaseEffects> {
+        None
+    }
+
+    /// Set up what draws the effect's frames, the keyboard starts off static with the profile's brightness
+    ///
+    /// Built-in effects have nothing to draw
+    fn renderer(&self, _profile: &Profile) -> Option<Box<dyn Renderer>> {
+        None
+    }
+
+    /// The renderer to use as part of a layer stack, where the keyboard can't play its own effects
+    fn layer_renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        self.renderer(profile)
+    }
+
+    fn name(&self) -> &'static str {
+        self.kind().into()
+    }
+
+    fn takes(&self, parameter: &Parameter) -> bool {
+        self.info().parameters.contains(parameter)
+    }
+
+    fn speed_range(&self) -> Option<RangeInclusive<u8>> {
+        self.info().parameters.iter().find_map(|parameter| match parameter {
+            Parameter::Speed(range) => Some(range.clone()),
+            _ => None,
+        })
+    }
+
+    fn is_built_in(&self) -> bool {
+        self.hardware_effect(Direction::default()).is_some()
+    }
+
+    /// Whether [`Effect::layer_renderer`] has anything to draw, the keyboard's own effects can't be mixed with others unless the app draws them too
+    fn can_be_layered(&self) -> bool {
+        !self.is_built_in()
+    }
+}
+
+/// Every effect, in the order they are listed in
+pub(crate) static EFFECTS: &[&dyn Effect] = &[
+    &Static,
+    &Breath,
+    &Smooth,
+    &Wave,
+    &Lightning,
+    &AmbientLight,
+    &SmoothWave,
+    &Swipe,
+    &Disco,
+    &Christmas,
+    &Fade,
+    &Temperature,
+    &Ripple,
+    &Visualizer,
+];
+
+/// The implementation behind a profile's effect
+pub(crate) fn find(kind: Effects) -> &'static dyn Effect {
+    // `Effects` only compares variants, ignoring the settings some of them carry
+    *EFFECTS.iter().find(|effect| effect.kind() == kind).expect("Every effect variant should be registered")
+}
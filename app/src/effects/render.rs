@@ -0,0 +1,164 @@
+use std::{collections::VecDeque, time::Duration};
+
+use legion_rgb_driver::{
+    color::ZoneColors,
+    transition::{ColorSpace, Easing, Transition},
+};
+
+use super::EffectError;
+
+/// How often effects are asked for a new frame unless they or `LEGION_KEYBOARD_TICK_RATE` say otherwise
+pub const DEFAULT_TICK_RATE: u32 = 30;
+
+/// What a renderer is told about the frame it is asked for
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Tick {
+    /// Time since the effect started
+    pub elapsed: Duration,
+    /// The time between two ticks
+    pub interval: Duration,
+    /// The colors currently displayed
+    pub previous: ZoneColors,
+}
+
+/// A frame drawn as part of a layer stack, along with how opaque each zone is
+#[derive(Clone, Copy, Debug)]
+pub struct LayerFrame {
+    pub colors: ZoneColors,
+    /// From 0 (see-through) to 1, per zone
+    pub alpha: [f32; 4],
+}
+
+impl LayerFrame {
+    pub fn opaque(colors: ZoneColors) -> Self {
+        Self { colors, alpha: [1.0; 4] }
+    }
+}
+
+/// Draws the frames of an effect, driven by the manager's clock
+///
+/// Renderers don't sleep or write to the keyboard themselves, anything they keep between frames is derived from the ticks they are given
+pub(crate) trait Renderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors;
+
+    /// The frame to use when drawn over other effects, effects that only light up parts of the keyboard can leave the rest see-through
+    fn render_layer(&mut self, tick: &Tick) -> LayerFrame {
+        LayerFrame::opaque(self.render(tick))
+    }
+
+    /// A tick rate of its own, for effects bound to an outside source
+    fn tick_rate(&self) -> Option<u32> {
+        None
+    }
+
+    /// Whether the effect has played out, checked after each frame is drawn
+    fn is_finished(&self, _tick: &Tick) -> bool {
+        false
+    }
+
+    /// Something that went wrong while drawing, picked up after each frame and reported through [`super::EffectManager::errors`]
+    fn take_error(&mut self) -> Option<EffectError> {
+        None
+    }
+}
+
+/// Stops the renderer it wraps after a while, for effects played as part of a custom effect
+pub(super) struct TimeLimit {
+    renderer: Box<dyn Renderer>,
+    limit: Duration,
+}
+
+impl TimeLimit {
+    pub fn new(renderer: Box<dyn Renderer>, limit: Duration) -> Self {
+        Self { renderer, limit }
+    }
+}
+
+impl Renderer for TimeLimit {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        self.renderer.render(tick)
+    }
+
+    fn render_layer(&mut self, tick: &Tick) -> LayerFrame {
+        self.renderer.render_layer(tick)
+    }
+
+    fn tick_rate(&self) -> Option<u32> {
+        self.renderer.tick_rate()
+    }
+
+    fn is_finished(&self, tick: &Tick) -> bool {
+        tick.elapsed >= self.limit || self.renderer.is_finished(tick)
+    }
+
+    fn take_error(&mut self) -> Option<EffectError> {
+        self.renderer.take_error()
+    }
+}
+
+/// A transition the step-based effects used to do, linear and in sRGB
+pub(super) fn linear(duration: Duration) -> Transition {
+    Transition::new(duration, Easing::Linear, ColorSpace::Srgb)
+}
+
+struct Keyframe {
+    colors: ZoneColors,
+    transition: Transition,
+    hold: Duration,
+}
+
+/// A queue of colors to go through one after the other, for effects made of scripted sequences
+pub(super) struct Timeline {
+    from: ZoneColors,
+    started: Duration,
+    keyframes: VecDeque<Keyframe>,
+}
+
+impl Timeline {
+    pub fn new(from: ZoneColors) -> Self {
+        Self {
+            from,
+            started: Duration::ZERO,
+            keyframes: VecDeque::new(),
+        }
+    }
+
+    /// Go to `colors` over `transition` once the previous keyframes are done, then stay there for `hold`
+    pub fn push(&mut self, colors: ZoneColors, transition: Transition, hold: Duration) {
+        self.keyframes.push_back(Keyframe { colors, transition, hold });
+    }
+
+    /// Show `colors` right away and stay there for `hold`
+    pub fn push_set(&mut self, colors: ZoneColors, hold: Duration) {
+        self.push(colors, linear(Duration::ZERO), hold);
+    }
+
+    /// Whether every keyframe was played by `elapsed`
+    pub fn is_finished(&mut self, elapsed: Duration) -> bool {
+        self.advance(elapsed);
+        self.keyframes.is_empty()
+    }
+
+    pub fn render(&mut self, elapsed: Duration) -> ZoneColors {
+        self.advance(elapsed);
+
+        match self.keyframes.front() {
+            Some(keyframe) => keyframe.transition.colors_at(&self.from, &keyframe.colors, elapsed.saturating_sub(self.started)),
+            None => self.from,
+        }
+    }
+
+    fn advance(&mut self, elapsed: Duration) {
+        while let Some(keyframe) = self.keyframes.front() {
+            let end = self.started + keyframe.transition.duration + keyframe.hold;
+
+            if end > elapsed {
+                break;
+            }
+
+            self.from = keyframe.colors;
+            self.started = end;
+            self.keyframes.pop_front();
+        }
+    }
+}
@@ -5,12 +5,19 @@ use std::{
         Arc,
     },
     thread,
-    time::{Duration, Instant},
+    time::Duration,
 };
 
+use crossbeam_channel::Receiver;
 use device_query::{DeviceEvents, Keycode};
+use legion_rgb_driver::color::ZoneColors;
 
-use crate::profile::Profile;
+use crate::{enums::Effects, profile::Profile};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter, SOFTWARE_SPEED_RANGE},
+    render::{LayerFrame, Renderer, Tick},
+};
 
 #[derive(Clone, Copy, PartialEq, Eq, Debug)]
 enum RippleMove {
@@ -20,10 +27,15 @@ enum RippleMove {
     Off,
 }
 
+enum Event {
+    KeyPress(Keycode),
+    KeyRelease(Keycode),
+}
+
 pub(super) struct Ripple;
 
 impl Ripple {
-    pub fn play(manager: &mut super::Inner, p: &Profile) {
+    fn key_zones() -> [Vec<Keycode>; 4] {
         // Welcome to the definition of i-don't-know-what-im-doing
         let keys_zone_1: [Keycode; 24] = [
             Keycode::Escape,
@@ -131,29 +143,34 @@ impl Ripple {
             Keycode::Numpad0,
         ];
 
-        let key_zones = [keys_zone_1.to_vec(), keys_zone_2.to_vec(), keys_zone_3.to_vec(), keys_zone_4.to_vec()];
+        [keys_zone_1.to_vec(), keys_zone_2.to_vec(), keys_zone_3.to_vec(), keys_zone_4.to_vec()]
+    }
+}
 
-        let stop_signals = manager.stop_signals.clone();
+struct RippleRenderer {
+    colors: ZoneColors,
+    speed: u8,
+    key_zones: [Vec<Keycode>; 4],
+    rx: Receiver<Event>,
+    kill_thread: Arc<AtomicBool>,
+    zone_pressed: [HashSet<Keycode>; 4],
+    zone_state: [RippleMove; 4],
+    last_step_time: Duration,
+}
 
+impl RippleRenderer {
+    fn new(p: &Profile) -> Self {
         let kill_thread = Arc::new(AtomicBool::new(false));
         let exit_thread = kill_thread.clone();
 
-        enum Event {
-            KeyPress(Keycode),
-            KeyRelease(Keycode),
-        }
-
         let (tx, rx) = crossbeam_channel::unbounded::<Event>();
 
         thread::spawn(move || {
             let state = device_query::DeviceState::new();
 
-            // tx_clone.send(Event::KeyPress(Keycode::Meta)).unwrap();
             let tx_clone = tx.clone();
 
             let guard = state.on_key_down(move |key| {
-                stop_signals.keyboard_stop_signal.store(true, Ordering::SeqCst);
-
                 let _ = tx_clone.send(Event::KeyPress(*key));
             });
 
@@ -173,67 +190,78 @@ impl Ripple {
             }
         });
 
-        let mut zone_pressed: [HashSet<Keycode>; 4] = [HashSet::new(), HashSet::new(), HashSet::new(), HashSet::new()];
-        let mut zone_state: [RippleMove; 4] = [RippleMove::Off, RippleMove::Off, RippleMove::Off, RippleMove::Off];
-
-        let mut last_step_time = Instant::now();
+        Self {
+            colors: p.zone_colors(),
+            speed: p.speed,
+            key_zones: Ripple::key_zones(),
+            rx,
+            kill_thread,
+            zone_pressed: [HashSet::new(), HashSet::new(), HashSet::new(), HashSet::new()],
+            zone_state: [RippleMove::Off, RippleMove::Off, RippleMove::Off, RippleMove::Off],
+            last_step_time: Duration::ZERO,
+        }
+    }
+}
 
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            match rx.try_recv() {
-                Ok(event) => match event {
-                    Event::KeyPress(key) => {
-                        for (i, zone) in key_zones.iter().enumerate() {
-                            if zone.contains(&key) {
-                                zone_pressed[i].insert(key);
-                            }
+impl Renderer for RippleRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        for event in self.rx.try_iter() {
+            match event {
+                Event::KeyPress(key) => {
+                    for (i, zone) in self.key_zones.iter().enumerate() {
+                        if zone.contains(&key) {
+                            self.zone_pressed[i].insert(key);
                         }
-
-                        manager.stop_signals.keyboard_stop_signal.store(false, Ordering::SeqCst);
                     }
-                    Event::KeyRelease(key) => {
-                        for (i, zone) in key_zones.iter().enumerate() {
-                            if zone.contains(&key) {
-                                zone_pressed[i].remove(&key);
-                            }
+                }
+                Event::KeyRelease(key) => {
+                    for (i, zone) in self.key_zones.iter().enumerate() {
+                        if zone.contains(&key) {
+                            self.zone_pressed[i].remove(&key);
                         }
                     }
-                },
-                Err(err) => {
-                    if let crossbeam_channel::TryRecvError::Disconnected = err {
-                        break;
-                    }
                 }
             }
+        }
 
-            zone_state = advance_zone_state(zone_state, &mut last_step_time, &p.speed);
+        self.zone_state = advance_zone_state(self.zone_state, &mut self.last_step_time, tick.elapsed, self.speed);
 
-            for (i, pressed) in zone_pressed.iter().enumerate() {
-                if !pressed.is_empty() {
-                    zone_state[i] = RippleMove::Center;
-                }
+        for (i, pressed) in self.zone_pressed.iter().enumerate() {
+            if !pressed.is_empty() {
+                self.zone_state[i] = RippleMove::Center;
             }
+        }
 
-            let rgb_array = p.rgb_array();
-            let mut final_arr: [u8; 12] = [0; 12];
+        let mut colors = ZoneColors::default();
 
-            for (i, ripple_move) in zone_state.iter().enumerate() {
-                if ripple_move != &RippleMove::Off {
-                    final_arr[(i * 3)..((i * 3) + 3)].copy_from_slice(&rgb_array[(i * 3)..((i * 3) + 3)]);
-                }
+        for (i, ripple_move) in self.zone_state.iter().enumerate() {
+            if ripple_move != &RippleMove::Off {
+                colors.0[i] = self.colors.0[i];
             }
-
-            manager.keyboard.transition_colors_to(&final_arr, 20, 0).unwrap();
-            thread::sleep(Duration::from_millis(50));
         }
 
-        kill_thread.store(true, Ordering::SeqCst);
+        colors
+    }
+
+    fn render_layer(&mut self, tick: &Tick) -> LayerFrame {
+        let colors = self.render(tick);
+
+        // Only the ripples themselves cover what is below
+        LayerFrame {
+            colors,
+            alpha: self.zone_state.map(|ripple_move| if ripple_move == RippleMove::Off { 0.0 } else { 1.0 }),
+        }
     }
 }
 
-fn advance_zone_state(zone_state: [RippleMove; 4], last_step_time: &mut Instant, speed: &u8) -> [RippleMove; 4] {
-    let now = Instant::now();
+impl Drop for RippleRenderer {
+    fn drop(&mut self) {
+        self.kill_thread.store(true, Ordering::SeqCst);
+    }
+}
 
-    if now - *last_step_time > Duration::from_millis((200 / *speed) as u64) {
+fn advance_zone_state(zone_state: [RippleMove; 4], last_step_time: &mut Duration, now: Duration, speed: u8) -> [RippleMove; 4] {
+    if now - *last_step_time > Duration::from_millis(u64::from(200 / speed)) {
         let mut new_state: [RippleMove; 4] = [RippleMove::Off, RippleMove::Off, RippleMove::Off, RippleMove::Off];
 
         *last_step_time = now;
@@ -277,3 +305,22 @@ fn advance_zone_state(zone_state: [RippleMove; 4], last_step_time: &mut Instant,
         zone_state
     }
 }
+
+static INFO: EffectInfo = EffectInfo {
+    description: "Colors spreading out from the keys being pressed",
+    parameters: &[Parameter::Colors, Parameter::Speed(SOFTWARE_SPEED_RANGE)],
+};
+
+impl Effect for Ripple {
+    fn kind(&self) -> Effects {
+        Effects::Ripple
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(RippleRenderer::new(profile)))
+    }
+}
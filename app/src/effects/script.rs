@@ -0,0 +1,307 @@
+use std::{collections::HashSet, time::Duration};
+
+use device_query::{DeviceQuery, DeviceState, Keycode};
+use legion_rgb_driver::color::{Rgb, ZoneColors};
+use rhai::{module_resolvers::DummyModuleResolver, Array, CallFnOptions, Dynamic, Engine, EvalAltResult, Map, Position, Scope, AST};
+use sysinfo::{ComponentExt, CpuExt, System, SystemExt};
+
+use crate::diagnostics::Diagnostic;
+
+use super::{
+    render::{Renderer, Tick},
+    EffectError,
+};
+
+/// How often the system metrics handed to scripts are read again
+const METRICS_INTERVAL: Duration = Duration::from_millis(500);
+
+/// An effect written in Rhai, see the README for what scripts are given and have to return
+#[derive(Clone, Debug)]
+pub struct Script {
+    ast: AST,
+}
+
+impl Script {
+    pub fn compile(source: &str) -> Result<Self, Diagnostic> {
+        let ast = sandboxed_engine(false).compile(source).map_err(|err| diagnostic_at(err.1, err.0.to_string()))?;
+
+        if !ast.iter_functions().any(|function| function.name == "render" && function.params.len() == 1) {
+            return Err(Diagnostic::new("the script has no render(ctx) function"));
+        }
+
+        Ok(Self { ast })
+    }
+
+    /// Set the script up to be played, `echo_output` shows what it prints on the console
+    ///
+    /// This runs the script's top level and opens the key listener, so it's only done once the effect starts
+    pub(super) fn renderer(&self, echo_output: bool) -> Result<ScriptRenderer, String> {
+        let device_state = DeviceState::checked_new().ok_or("could not listen to the keys, no display is available")?;
+
+        let engine = sandboxed_engine(echo_output);
+        let mut scope = Scope::new();
+
+        // Top level statements run once, functions can't see their variables but `this` is kept between frames
+        engine.run_ast_with_scope(&mut scope, &self.ast).map_err(|err| err.to_string())?;
+
+        Ok(ScriptRenderer {
+            engine,
+            ast: self.ast.clone(),
+            scope,
+            state: Dynamic::from_map(Map::new()),
+            device_state,
+            held_keys: HashSet::new(),
+            sys: System::new(),
+            metrics: Map::new(),
+            next_metrics_refresh: Duration::ZERO,
+            last_elapsed: Duration::ZERO,
+            last_error: None,
+            pending_error: None,
+        })
+    }
+}
+
+fn diagnostic_at(position: Position, message: String) -> Diagnostic {
+    match (position.line(), position.position()) {
+        (Some(line), Some(column)) => Diagnostic::at_position(line, column, message),
+        _ => Diagnostic::new(message),
+    }
+}
+
+/// Scripts can't touch the file system or load modules, and are cut off if they run for too long
+///
+/// What they print is dropped unless `echo_output` is set, the GUI has no console to show it on
+fn sandboxed_engine(echo_output: bool) -> Engine {
+    let mut engine = Engine::new();
+
+    engine.set_module_resolver(DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+
+    engine.set_max_operations(200_000);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(4096);
+    engine.set_max_array_size(1024);
+    engine.set_max_map_size(1024);
+
+    if echo_output {
+        engine.on_print(|text| println!("[script] {text}"));
+        engine.on_debug(|text, _, position| println!("[script] {position:?}: {text}"));
+    } else {
+        engine.on_print(|_| {});
+        engine.on_debug(|_, _, _| {});
+    }
+
+    engine
+}
+
+pub(super) struct ScriptRenderer {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    state: Dynamic,
+    device_state: DeviceState,
+    held_keys: HashSet<Keycode>,
+    sys: System,
+    metrics: Map,
+    next_metrics_refresh: Duration,
+    last_elapsed: Duration,
+    /// Only report an error once instead of on every frame
+    last_error: Option<String>,
+    /// An error not yet picked up by [`Renderer::take_error`]
+    pending_error: Option<String>,
+}
+
+impl ScriptRenderer {
+    fn try_render(&mut self, tick: &Tick) -> Result<ZoneColors, Box<EvalAltResult>> {
+        let ctx = self.context(tick);
+        let options = CallFnOptions::new().eval_ast(false).bind_this_ptr(&mut self.state);
+
+        let frame: Dynamic = self.engine.call_fn_with_options(options, &mut self.scope, &self.ast, "render", (ctx,))?;
+
+        to_zone_colors(frame).map_err(Into::into)
+    }
+
+    fn context(&mut self, tick: &Tick) -> Map {
+        let keys = self.device_state.get_keys();
+        let pressed: Array = keys.iter().filter(|key| !self.held_keys.contains(key)).map(key_name).collect();
+        self.held_keys = keys.into_iter().collect();
+
+        if tick.elapsed >= self.next_metrics_refresh {
+            self.refresh_metrics();
+            self.next_metrics_refresh = tick.elapsed + METRICS_INTERVAL;
+        }
+
+        let previous: Array = tick
+            .previous
+            .0
+            .iter()
+            .map(|color| Dynamic::from_array(<[u8; 3]>::from(*color).map(|c| Dynamic::from_int(i64::from(c))).to_vec()))
+            .collect();
+
+        let mut ctx = self.metrics.clone();
+        ctx.insert("time".into(), Dynamic::from_float(tick.elapsed.as_secs_f64()));
+        ctx.insert("delta".into(), Dynamic::from_float(tick.elapsed.saturating_sub(self.last_elapsed).as_secs_f64()));
+        ctx.insert("zones".into(), Dynamic::from_int(4));
+        ctx.insert("keys".into(), Dynamic::from_array(self.held_keys.iter().map(key_name).collect()));
+        ctx.insert("pressed".into(), Dynamic::from_array(pressed));
+        ctx.insert("previous".into(), Dynamic::from_array(previous));
+
+        self.last_elapsed = tick.elapsed;
+
+        ctx
+    }
+
+    fn refresh_metrics(&mut self) {
+        self.sys.refresh_cpu();
+        self.sys.refresh_memory();
+        self.sys.refresh_components_list();
+
+        let cpu_temp = self
+            .sys
+            .components()
+            .iter()
+            .find(|component| component.label().contains("Tctl"))
+            .map_or(Dynamic::UNIT, |component| Dynamic::from_float(f64::from(component.temperature())));
+
+        let memory_usage = if self.sys.total_memory() == 0 {
+            0.0
+        } else {
+            self.sys.used_memory() as f64 / self.sys.total_memory() as f64 * 100.0
+        };
+
+        self.metrics.insert("cpu_usage".into(), Dynamic::from_float(f64::from(self.sys.global_cpu_info().cpu_usage())));
+        self.metrics.insert("cpu_temp".into(), cpu_temp);
+        self.metrics.insert("memory_usage".into(), Dynamic::from_float(memory_usage));
+    }
+}
+
+impl Renderer for ScriptRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        match self.try_render(tick) {
+            Ok(colors) => colors,
+            Err(err) => {
+                let err = err.to_string();
+
+                if self.last_error.as_ref() != Some(&err) {
+                    self.pending_error = Some(err.clone());
+                    self.last_error = Some(err);
+                }
+
+                tick.previous
+            }
+        }
+    }
+
+    fn take_error(&mut self) -> Option<EffectError> {
+        self.pending_error.take().map(EffectError::Script)
+    }
+}
+
+fn key_name(key: &Keycode) -> Dynamic {
+    Dynamic::from(format!("{key:?}"))
+}
+
+/// Scripts return 4 colors, each either an `[r, g, b]` array or a hex string
+fn to_zone_colors(frame: Dynamic) -> Result<ZoneColors, String> {
+    let type_name = frame.type_name();
+    let zones = frame.try_cast::<Array>().ok_or_else(|| format!("render() must return an array of 4 colors, got {type_name}"))?;
+
+    if zones.len() != 4 {
+        return Err(format!("render() must return 4 colors, got {}", zones.len()));
+    }
+
+    let mut colors = ZoneColors::default();
+
+    for (i, zone) in zones.into_iter().enumerate() {
+        colors.0[i] = to_rgb(zone).map_err(|err| format!("Zone {}: {err}", i + 1))?;
+    }
+
+    Ok(colors)
+}
+
+fn to_rgb(color: Dynamic) -> Result<Rgb, String> {
+    if color.is_string() {
+        let hex = color.into_string().unwrap_or_default();
+        return Rgb::from_hex(&hex).map_err(|err| err.to_string());
+    }
+
+    let type_name = color.type_name();
+    let channels = color
+        .try_cast::<Array>()
+        .filter(|channels| channels.len() == 3)
+        .ok_or_else(|| format!("expected [r, g, b] or a hex string, got {type_name}"))?;
+
+    let mut rgb = [0; 3];
+    for (channel, value) in rgb.iter_mut().zip(channels) {
+        let value = if value.is_float() {
+            value.as_float().unwrap_or_default().round() as i64
+        } else {
+            value.as_int().map_err(|_| "color channels must be numbers")?
+        };
+
+        *channel = value.clamp(0, 255) as u8;
+    }
+
+    Ok(Rgb::from(rgb))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn endless_scripts_are_cut_off() {
+        let result = sandboxed_engine(false).run("loop { }");
+
+        assert!(matches!(result.as_deref(), Err(EvalAltResult::ErrorTooManyOperations(_))), "{result:?}");
+    }
+
+    #[test]
+    fn runaway_recursion_is_cut_off() {
+        let result = sandboxed_engine(false).run("fn deeper(n) { deeper(n + 1) } deeper(0);");
+
+        assert!(matches!(result.as_deref(), Err(EvalAltResult::ErrorStackOverflow(_))), "{result:?}");
+    }
+
+    #[test]
+    fn modules_cannot_be_loaded() {
+        let result = sandboxed_engine(false).run(r#"import "other" as other;"#);
+
+        assert!(matches!(result.as_deref(), Err(EvalAltResult::ErrorModuleNotFound(..))), "{result:?}");
+    }
+
+    #[test]
+    fn a_missing_render_function_is_reported() {
+        let err = Script::compile("fn draw(ctx) { [0, 0, 0, 0] }").unwrap_err();
+
+        assert_eq!(err.message, "the script has no render(ctx) function");
+    }
+
+    #[test]
+    fn syntax_errors_are_reported_where_they_are() {
+        let err = Script::compile("fn render(ctx) {\n    let = 1;\n}").unwrap_err();
+
+        assert_eq!(err.position.map(|(line, _)| line), Some(2));
+    }
+
+    #[test]
+    fn frames_are_read_as_zone_colors() {
+        let frame = sandboxed_engine(false).eval::<Dynamic>(r##"[[255, 0, 0], "#00ff00", [0.4, 300, -5], "00f"]"##).unwrap();
+
+        assert_eq!(
+            to_zone_colors(frame),
+            Ok(ZoneColors([Rgb::new(255, 0, 0), Rgb::new(0, 255, 0), Rgb::new(0, 255, 0), Rgb::new(0, 0, 255)]))
+        );
+    }
+
+    #[test]
+    fn malformed_frames_are_rejected() {
+        let engine = sandboxed_engine(false);
+
+        assert!(to_zone_colors(engine.eval::<Dynamic>("42").unwrap()).is_err());
+        assert!(to_zone_colors(engine.eval::<Dynamic>("[[0, 0, 0]]").unwrap()).is_err());
+        assert!(to_zone_colors(engine.eval::<Dynamic>("[[0, 0], [0, 0, 0], [0, 0, 0], [0, 0, 0]]").unwrap()).is_err());
+        assert!(to_zone_colors(engine.eval::<Dynamic>(r#"["red", [0, 0, 0], [0, 0, 0], [0, 0, 0]]"#).unwrap()).is_err());
+    }
+}
@@ -1,29 +1,105 @@
-use std::{sync::atomic::Ordering, thread, time::Duration};
+use std::time::Duration;
 
-use crate::{enums::Direction, profile::Profile};
+use legion_rgb_driver::{
+    color::ZoneColors,
+    transition::{ColorSpace, Easing, Transition},
+};
+
+use crate::{
+    enums::{Direction, Effects},
+    profile::{self, Profile},
+};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter, SOFTWARE_SPEED_RANGE},
+    render::{Renderer, Tick},
+};
+
+/// How long the colors stay in place between two moves
+const PAUSE: Duration = Duration::from_millis(20);
 
 pub(super) struct Swipe;
 
-impl Swipe {
-    pub fn play(manager: &mut super::Inner, p: &Profile) {
-        let mut rgb_array = p.rgb_array();
+pub(super) struct SmoothWave;
 
-        while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-            if manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-                break;
-            }
+struct SwipeRenderer {
+    colors: ZoneColors,
+    direction: Direction,
+    transition: Transition,
+}
 
-            match p.direction {
-                Direction::Left => rgb_array.rotate_right(3),
-                Direction::Right => rgb_array.rotate_left(3),
-            }
+impl SwipeRenderer {
+    fn new(profile: &Profile) -> Self {
+        Self {
+            colors: profile.zone_colors(),
+            direction: profile.direction,
+            // Blending in OKLab keeps neighbouring colors from going through muddy in-betweens
+            transition: Transition::new(Duration::from_millis(u64::from(150 / profile.speed) * 10), Easing::EaseInOut, ColorSpace::Oklab),
+        }
+    }
 
-            manager.keyboard.transition_colors_to(&rgb_array, 150 / p.speed, 10).unwrap();
+    /// The colors after having moved `moves` times
+    fn moved(&self, moves: u128) -> ZoneColors {
+        let mut colors = self.colors;
 
-            if manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-                break;
+        for _ in 0..moves % 4 {
+            match self.direction {
+                Direction::Left => colors.rotate_right(),
+                Direction::Right => colors.rotate_left(),
             }
-            thread::sleep(Duration::from_millis(20));
         }
+
+        colors
+    }
+}
+
+impl Renderer for SwipeRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        let period = self.transition.duration + PAUSE;
+        let moves = tick.elapsed.as_nanos() / period.as_nanos();
+        let into_move = tick.elapsed - period * moves as u32;
+
+        self.transition.colors_at(&self.moved(moves), &self.moved(moves + 1), into_move)
+    }
+}
+
+static SWIPE: EffectInfo = EffectInfo {
+    description: "The zone colors moving across the keyboard",
+    parameters: &[Parameter::Colors, Parameter::Speed(SOFTWARE_SPEED_RANGE), Parameter::Direction],
+};
+
+impl Effect for Swipe {
+    fn kind(&self) -> Effects {
+        Effects::Swipe
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &SWIPE
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(SwipeRenderer::new(profile)))
+    }
+}
+
+static SMOOTH_WAVE: EffectInfo = EffectInfo {
+    description: "A smoother take on the rainbow wave",
+    parameters: &[Parameter::Speed(SOFTWARE_SPEED_RANGE), Parameter::Direction],
+};
+
+impl Effect for SmoothWave {
+    fn kind(&self) -> Effects {
+        Effects::SmoothWave
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &SMOOTH_WAVE
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        let mut profile = profile.clone();
+        profile.rgb_zones = profile::arr_to_zones([255, 0, 0, 0, 255, 0, 0, 0, 255, 255, 0, 255]);
+
+        Some(Box::new(SwipeRenderer::new(&profile)))
     }
 }
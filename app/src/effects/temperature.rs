@@ -1,42 +1,96 @@
-use std::{sync::atomic::Ordering, thread, time::Duration};
+use std::time::Duration;
 
+use legion_rgb_driver::color::ZoneColors;
 use sysinfo::{ComponentExt, System, SystemExt};
 
+use crate::{enums::Effects, profile::Profile};
+
+use super::{
+    registry::{Effect, EffectInfo},
+    render::{Renderer, Tick},
+};
+
+/// How often the temperature is read again
+const REFRESH_INTERVAL: Duration = Duration::from_millis(200);
+
 pub(super) struct Temperature;
 
-impl Temperature {
-    pub fn play(manager: &mut super::Inner) {
-        let safe_temp = 20.0;
-        let ramp_boost = 1.6;
-        let temp_cool: [f32; 12] = [0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0];
-        let temp_hot: [f32; 12] = [255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0];
+struct TemperatureRenderer {
+    sys: System,
+    /// The CPU's sensor, nothing is drawn without one
+    component: Option<usize>,
+    color_differences: [f32; 12],
+    next_refresh: Duration,
+}
 
+impl TemperatureRenderer {
+    const SAFE_TEMP: f32 = 20.0;
+    const RAMP_BOOST: f32 = 1.6;
+    const TEMP_COOL: [f32; 12] = [0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0];
+    const TEMP_HOT: [f32; 12] = [255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0, 255.0, 0.0, 0.0];
+
+    fn new() -> Self {
         let mut color_differences: [f32; 12] = [0.0; 12];
         for index in 0..12 {
-            color_differences[index] = temp_hot[index] - temp_cool[index];
+            color_differences[index] = Self::TEMP_HOT[index] - Self::TEMP_COOL[index];
         }
 
         let mut sys = System::new_all();
         sys.refresh_all();
 
-        for component in sys.components_mut() {
-            if component.label().contains("Tctl") {
-                while !manager.stop_signals.manager_stop_signal.load(Ordering::SeqCst) {
-                    component.refresh();
-                    let mut adjusted_temp = component.temperature() - safe_temp;
-                    if adjusted_temp < 0.0 {
-                        adjusted_temp = 0.0;
-                    }
-                    let temp_percent = (adjusted_temp / 100.0) * ramp_boost;
-
-                    let mut target = [0.0; 12];
-                    for index in 0..12 {
-                        target[index] = color_differences[index].mul_add(temp_percent, temp_cool[index]);
-                    }
-                    manager.keyboard.transition_colors_to(&target.map(|val| val as u8), 5, 1).unwrap();
-                    thread::sleep(Duration::from_millis(200));
-                }
-            }
+        let component = sys.components().iter().position(|component| component.label().contains("Tctl"));
+
+        Self {
+            sys,
+            component,
+            color_differences,
+            next_refresh: Duration::ZERO,
+        }
+    }
+}
+
+impl Renderer for TemperatureRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        let Some(component) = self.component.and_then(|index| self.sys.components_mut().get_mut(index)) else {
+            return tick.previous;
+        };
+
+        if tick.elapsed < self.next_refresh {
+            return tick.previous;
+        }
+        self.next_refresh = tick.elapsed + REFRESH_INTERVAL;
+
+        component.refresh();
+        let mut adjusted_temp = component.temperature() - Self::SAFE_TEMP;
+        if adjusted_temp < 0.0 {
+            adjusted_temp = 0.0;
         }
+        let temp_percent = (adjusted_temp / 100.0) * Self::RAMP_BOOST;
+
+        let mut target = [0.0; 12];
+        for index in 0..12 {
+            target[index] = self.color_differences[index].mul_add(temp_percent, Self::TEMP_COOL[index]);
+        }
+
+        ZoneColors::from_array(target.map(|val| val as u8))
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "Going from green to red as the CPU heats up",
+    parameters: &[],
+};
+
+impl Effect for Temperature {
+    fn kind(&self) -> Effects {
+        Effects::Temperature
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, _profile: &Profile) -> Option<Box<dyn Renderer>> {
+        Some(Box::new(TemperatureRenderer::new()))
     }
 }
@@ -0,0 +1,333 @@
+use std::{collections::VecDeque, ops::RangeInclusive, sync::Arc, time::Duration};
+
+use legion_rgb_driver::color::{Rgb, ZoneColors};
+use rustfft::{num_complex::Complex, Fft, FftPlanner};
+
+use crate::{
+    audio::{self, AudioStream},
+    enums::{Effects, Palette, VisualizerMode},
+    profile::Profile,
+};
+
+use super::{
+    registry::{Effect, EffectInfo, Parameter},
+    render::{LayerFrame, Renderer, Tick},
+    EffectError,
+};
+
+pub const SENSITIVITY_RANGE: RangeInclusive<f32> = 0.1..=5.0;
+pub const SMOOTHING_RANGE: RangeInclusive<f32> = 0.0..=0.95;
+
+/// About 46ms of audio at 44.1kHz, enough to tell the bass notes apart
+const FFT_SIZE: usize = 2048;
+
+/// The frequencies (in Hz) each zone follows, from left to right
+const BANDS: [(f32, f32); 4] = [(20.0, 250.0), (250.0, 1000.0), (1000.0, 4000.0), (4000.0, 16000.0)];
+
+/// The quietest a band can be and still light up, at a sensitivity of 1
+const NOISE_FLOOR_DB: f32 = -50.0;
+
+/// How much louder than its recent average the bass has to get to count as a beat
+const BEAT_THRESHOLD: f32 = 1.4;
+
+/// How far back the recent average of the bass goes
+const BEAT_HISTORY: Duration = Duration::from_secs(1);
+
+/// The tick rate the smoothing is tuned for, so that it fades at the same pace at any other
+const SMOOTHING_TICK_RATE: f32 = 30.0;
+
+pub(super) struct Visualizer;
+
+/// Splits the sound into the frequency bands the zones follow
+struct Analyzer {
+    fft: Arc<dyn Fft<f32>>,
+    window: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        // Hann window, keeps the edges of the buffer from smearing the spectrum
+        let window = (0..FFT_SIZE).map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / (FFT_SIZE - 1) as f32).cos()).collect();
+
+        Self {
+            fft: FftPlanner::new().plan_fft_forward(FFT_SIZE),
+            window,
+            spectrum: vec![Complex::default(); FFT_SIZE],
+        }
+    }
+
+    /// The peak amplitude of each band in the last [`FFT_SIZE`] samples, a full scale sine coming out at about 1
+    fn band_amplitudes(&mut self, samples: &[f32], sample_rate: u32) -> [f32; 4] {
+        for ((bin, sample), weight) in self.spectrum.iter_mut().zip(samples).zip(&self.window) {
+            *bin = Complex::new(sample * weight, 0.0);
+        }
+
+        self.fft.process(&mut self.spectrum);
+
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        let nyquist = FFT_SIZE / 2;
+
+        BANDS.map(|(low, high)| {
+            let start = ((low / bin_width).ceil() as usize).max(1);
+            let end = ((high / bin_width) as usize).min(nyquist);
+
+            if start > end {
+                return 0.0;
+            }
+
+            // The window halves the amplitude on top of the FFT's scaling
+            self.spectrum[start..=end].iter().map(|bin| bin.norm()).fold(0.0, f32::max) / (FFT_SIZE as f32 / 4.0)
+        })
+    }
+}
+
+/// From 0 (at the noise floor or below) to 1 (full scale)
+fn level(amplitude: f32, sensitivity: f32) -> f32 {
+    let db = 20.0 * (amplitude * sensitivity).max(f32::EPSILON).log10();
+
+    (1.0 - db / NOISE_FLOOR_DB).clamp(0.0, 1.0)
+}
+
+struct VisualizerRenderer {
+    stream: AudioStream,
+    analyzer: Analyzer,
+    samples: Vec<f32>,
+    mode: VisualizerMode,
+    sensitivity: f32,
+    smoothing: f32,
+    palette: [Rgb; 4],
+    /// How lit up each zone is, from 0 to 1
+    levels: [f32; 4],
+    /// The loudness of the bass over the last [`BEAT_HISTORY`]
+    bass_history: VecDeque<(Duration, f32)>,
+}
+
+impl VisualizerRenderer {
+    fn new(stream: AudioStream, mode: VisualizerMode, sensitivity: f32, smoothing: f32, palette: [Rgb; 4]) -> Self {
+        Self {
+            stream,
+            analyzer: Analyzer::new(),
+            samples: vec![0.0; FFT_SIZE],
+            mode,
+            sensitivity,
+            smoothing,
+            palette,
+            levels: [0.0; 4],
+            bass_history: VecDeque::new(),
+        }
+    }
+
+    fn band_amplitudes(&mut self) -> [f32; 4] {
+        self.stream.latest(&mut self.samples);
+
+        self.analyzer.band_amplitudes(&self.samples, self.stream.sample_rate())
+    }
+
+    fn is_beat(&mut self, elapsed: Duration, bass: f32) -> bool {
+        self.bass_history.push_back((elapsed, bass));
+        while self.bass_history.front().is_some_and(|(time, _)| elapsed.saturating_sub(*time) > BEAT_HISTORY) {
+            self.bass_history.pop_front();
+        }
+
+        let average = self.bass_history.iter().map(|(_, bass)| bass).sum::<f32>() / self.bass_history.len() as f32;
+
+        bass > average * BEAT_THRESHOLD && level(bass, self.sensitivity) > 0.2
+    }
+}
+
+impl Renderer for VisualizerRenderer {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        let amplitudes = self.band_amplitudes();
+
+        // Light up right away but fade out slowly
+        let decay = self.smoothing.powf(tick.interval.as_secs_f32() * SMOOTHING_TICK_RATE);
+        let fade = |level: f32, target: f32| if target >= level { target } else { level * decay + target * (1.0 - decay) };
+
+        match self.mode {
+            VisualizerMode::Spectrum => {
+                let targets = amplitudes.map(|amplitude| level(amplitude, self.sensitivity));
+
+                for (level, target) in self.levels.iter_mut().zip(targets) {
+                    *level = fade(*level, target);
+                }
+            }
+            VisualizerMode::Beat => {
+                let target = if self.is_beat(tick.elapsed, amplitudes[0]) { 1.0 } else { 0.0 };
+                self.levels = [fade(self.levels[0], target); 4];
+            }
+        }
+
+        ZoneColors(std::array::from_fn(|i| self.palette[i].dimmed((self.levels[i] * 100.0).round() as u8)))
+    }
+
+    /// The quiet zones let the layers below show through
+    fn render_layer(&mut self, tick: &Tick) -> LayerFrame {
+        let colors = self.render(tick);
+
+        LayerFrame { colors, alpha: self.levels }
+    }
+
+    fn take_error(&mut self) -> Option<EffectError> {
+        self.stream.take_error().map(|err| EffectError::Audio(audio::summary(&err)))
+    }
+}
+
+/// Stands in for the visualizer when there is nothing to listen to, leaving the keyboard as it is and reporting why once
+struct Silent(Option<EffectError>);
+
+impl Renderer for Silent {
+    fn render(&mut self, tick: &Tick) -> ZoneColors {
+        tick.previous
+    }
+
+    fn render_layer(&mut self, tick: &Tick) -> LayerFrame {
+        LayerFrame {
+            colors: tick.previous,
+            alpha: [0.0; 4],
+        }
+    }
+
+    fn take_error(&mut self) -> Option<EffectError> {
+        self.0.take()
+    }
+}
+
+fn palette_colors(palette: Palette, profile: &Profile) -> [Rgb; 4] {
+    let hex = |colors: [&str; 4]| colors.map(|color| Rgb::from_hex(color).unwrap_or_default());
+
+    match palette {
+        Palette::Zones => profile.zone_colors().0,
+        Palette::Rainbow => std::array::from_fn(|i| Rgb::from_hsv(i as f32 * 80.0, 1.0, 1.0)),
+        Palette::Fire => hex(["#ff1000", "#ff4000", "#ff8000", "#ffc000"]),
+        Palette::Ocean => hex(["#0010ff", "#0060ff", "#00b0ff", "#00ffd0"]),
+    }
+}
+
+static INFO: EffectInfo = EffectInfo {
+    description: "Reacts to the sound being played, see LEGION_KEYBOARD_AUDIO_SOURCE to pick what it listens to",
+    parameters: &[Parameter::Colors, Parameter::VisualizerMode, Parameter::Sensitivity, Parameter::Smoothing, Parameter::Palette],
+};
+
+impl Effect for Visualizer {
+    fn kind(&self) -> Effects {
+        Effects::Visualizer {
+            mode: VisualizerMode::default(),
+            sensitivity: 1.0,
+            smoothing: 0.6,
+            palette: Palette::default(),
+        }
+    }
+
+    fn info(&self) -> &'static EffectInfo {
+        &INFO
+    }
+
+    fn renderer(&self, profile: &Profile) -> Option<Box<dyn Renderer>> {
+        let Effects::Visualizer {
+            mode,
+            sensitivity,
+            smoothing,
+            palette,
+        } = profile.effect
+        else {
+            unreachable!("The visualizer was given another effect's profile")
+        };
+
+        let stream = match AudioStream::start(FFT_SIZE) {
+            Ok(stream) => stream,
+            Err(err) => return Some(Box::new(Silent(Some(EffectError::Audio(audio::summary(&err)))))),
+        };
+
+        Some(Box::new(VisualizerRenderer::new(
+            stream,
+            mode,
+            sensitivity.clamp(*SENSITIVITY_RANGE.start(), *SENSITIVITY_RANGE.end()),
+            smoothing.clamp(*SMOOTHING_RANGE.start(), *SMOOTHING_RANGE.end()),
+            palette_colors(palette, profile),
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        env, fs, process,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use crate::audio::{wav::WavSource, AudioSource};
+
+    use super::*;
+
+    const SAMPLE_RATE: u32 = 44_100;
+
+    /// Tests run in parallel, each file gets a name of its own
+    static NEXT_FILE: AtomicUsize = AtomicUsize::new(0);
+
+    /// The band levels of a sine written to a WAV file and read back the way the visualizer would
+    fn sine_levels(frequency: f32, amplitude: f32) -> [f32; 4] {
+        let path = env::temp_dir().join(format!("legion-rgb-sine-{}-{}.wav", process::id(), NEXT_FILE.fetch_add(1, Ordering::Relaxed)));
+
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: SAMPLE_RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..FFT_SIZE {
+            let sample = amplitude * (2.0 * std::f32::consts::PI * frequency * i as f32 / SAMPLE_RATE as f32).sin();
+            writer.write_sample((sample * f32::from(i16::MAX)) as i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let source = WavSource::open(&path);
+        fs::remove_file(&path).unwrap();
+        let mut source = source.unwrap();
+
+        let mut samples = vec![0.0; FFT_SIZE];
+        source.read(&mut samples).unwrap();
+
+        Analyzer::new().band_amplitudes(&samples, source.sample_rate()).map(|amplitude| level(amplitude, 1.0))
+    }
+
+    #[test]
+    fn a_sine_lights_up_its_band_only() {
+        for (band, frequency) in [100.0, 440.0, 2000.0, 8000.0].into_iter().enumerate() {
+            let levels = sine_levels(frequency, 0.5);
+
+            for (i, level) in levels.into_iter().enumerate() {
+                if i == band {
+                    assert!(level > 0.8, "{frequency}Hz: band {i} is at {level}");
+                } else {
+                    assert!(level < 0.2, "{frequency}Hz: band {i} is at {level}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn quieter_sines_are_dimmer() {
+        let loud = sine_levels(440.0, 0.5)[1];
+        let quiet = sine_levels(440.0, 0.05)[1];
+
+        // A tenth of the amplitude is 20dB down, out of the 50 between the noise floor and full scale
+        assert!((loud - quiet - 0.4).abs() < 0.05, "{loud} then {quiet}");
+    }
+
+    #[test]
+    fn silence_stays_dark() {
+        assert_eq!(sine_levels(440.0, 0.0), [0.0; 4]);
+    }
+
+    #[test]
+    fn levels_follow_the_sensitivity() {
+        assert_eq!(level(1.0, 1.0), 1.0);
+        assert_eq!(level(0.0, 1.0), 0.0);
+        assert!((level(0.1, 1.0) - 0.6).abs() < 1e-4);
+        assert!((level(0.1, 2.0) - level(0.2, 1.0)).abs() < 1e-4);
+    }
+}
@@ -1,4 +1,10 @@
-use crate::{effects::custom_effect::CustomEffect, profile::Profile};
+use crate::{
+    effects::{
+        custom_effect::CustomEffect,
+        registry::{self, Parameter},
+    },
+    profile::Profile,
+};
 use serde::{Deserialize, Serialize};
 use strum_macros::{Display, EnumIter, EnumString, IntoStaticStr};
 
@@ -21,6 +27,12 @@ pub enum Effects {
     Fade,
     Temperature,
     Ripple,
+    Visualizer {
+        mode: VisualizerMode,
+        sensitivity: f32,
+        smoothing: f32,
+        palette: Palette,
+    },
 }
 
 impl PartialEq for Effects {
@@ -29,25 +41,23 @@ impl PartialEq for Effects {
     }
 }
 
+/// Shorthands for what the effect's registry entry says
 #[allow(dead_code)]
 impl Effects {
     pub fn takes_color_array(self) -> bool {
-        matches!(self, Self::Static | Self::Breath | Self::Lightning | Self::Swipe { .. } | Self::Fade | Self::Ripple)
+        registry::find(self).takes(&Parameter::Colors)
     }
 
     pub fn takes_direction(self) -> bool {
-        matches!(self, Self::Wave | Self::SmoothWave | Self::Swipe { .. })
+        registry::find(self).takes(&Parameter::Direction)
     }
 
     pub fn takes_speed(self) -> bool {
-        matches!(
-            self,
-            Self::Breath | Self::Smooth | Self::Wave | Self::Lightning | Self::SmoothWave | Self::Swipe | Self::Disco | Self::Fade | Self::Ripple
-        )
+        registry::find(self).speed_range().is_some()
     }
 
     pub fn is_built_in(self) -> bool {
-        matches!(self, Self::Static | Self::Breath | Self::Smooth | Self::Wave)
+        registry::find(self).is_built_in()
     }
 }
 
@@ -65,6 +75,57 @@ pub enum Brightness {
     High,
 }
 
+/// What the visualizer reacts to
+#[derive(Clone, Copy, EnumString, Serialize, Deserialize, Debug, EnumIter, IntoStaticStr, PartialEq, Eq, Default)]
+pub enum VisualizerMode {
+    /// Each zone follows a part of the spectrum, from the bass on the left to the treble on the right
+    #[default]
+    Spectrum,
+    /// The whole keyboard flashes on the beat
+    Beat,
+}
+
+/// The colors the visualizer lights the zones with
+#[derive(Clone, Copy, EnumString, Serialize, Deserialize, Debug, EnumIter, IntoStaticStr, PartialEq, Eq, Default)]
+pub enum Palette {
+    /// The ones picked for the zones
+    #[default]
+    Zones,
+    Rainbow,
+    Fire,
+    Ocean,
+}
+
+/// How a layer is combined with what is below it
+#[derive(Clone, Copy, EnumString, Serialize, Deserialize, Debug, EnumIter, IntoStaticStr, PartialEq, Eq, Default)]
+pub enum BlendMode {
+    /// Replace the colors below
+    #[default]
+    Normal,
+    /// Brighten by adding the colors together
+    Add,
+    /// Darken by multiplying the colors, white leaving the colors below untouched
+    Multiply,
+    /// Keep the brightest of each channel
+    Max,
+    /// Brighten like two projectors pointed at the same spot, gentler than adding
+    Screen,
+}
+
+/// What the keyboard is left showing once the app closes
+#[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Default)]
+pub enum ExitBehavior {
+    /// Leave whatever the last effect displayed
+    #[default]
+    Keep,
+    /// Go back to the state the keyboard reported on startup, models that can't be read from are left as is
+    Restore,
+    /// Switch to a profile, effects that aren't built into the keyboard only leave their colors behind
+    Profile(Profile),
+    /// Turn the lights off
+    Off,
+}
+
 #[derive(Debug)]
 pub enum Message {
     CustomEffect { effect: CustomEffect },
@@ -1,9 +1,12 @@
 use eframe::egui::{ComboBox, Slider, Ui};
-use legion_rgb_driver::SPEED_RANGE;
 use strum::IntoEnumIterator;
 
 use crate::{
-    enums::{Brightness, Direction, Effects},
+    effects::{
+        registry::{self, Parameter, SOFTWARE_SPEED_RANGE},
+        visualizer::{SENSITIVITY_RANGE, SMOOTHING_RANGE},
+    },
+    enums::{Brightness, Direction, Effects, Palette, VisualizerMode},
     profile::Profile,
 };
 
@@ -16,6 +19,8 @@ const COMBOBOX_WIDTH: f32 = 20.0;
 
 impl EffectOptions {
     pub fn show(&mut self, ui: &mut Ui, profile: &mut Profile, update_lights: &mut bool, spacing: &SpacingStyle) {
+        let effect = registry::find(profile.effect);
+
         ui.scope(|ui| {
             ui.style_mut().spacing.item_spacing = spacing.default;
 
@@ -32,8 +37,15 @@ impl EffectOptions {
                     }
                 });
 
+            ui.horizontal(|ui| {
+                // The keyboard's own rainbows don't go through the colors the app sends
+                let dimmable = effect.takes(&Parameter::Colors) || !effect.is_built_in();
+                *update_lights |= ui.add_enabled(dimmable, Slider::new(&mut profile.software_brightness, 0..=100).suffix("%")).changed();
+                ui.label("Dimming");
+            });
+
             ui.scope(|ui| {
-                ui.set_enabled(profile.effect.takes_direction());
+                ui.set_enabled(effect.takes(&Parameter::Direction));
 
                 ComboBox::from_label("Direction")
                     .width(COMBOBOX_WIDTH)
@@ -49,20 +61,79 @@ impl EffectOptions {
                     });
             });
 
-            if let Effects::AmbientLight { fps, saturation_boost: saturation } = &mut profile.effect {
-                ui.horizontal(|ui| {
-                    *update_lights |= ui.add(Slider::new(fps, 1..=60)).changed();
-                    ui.label("FPS");
-                });
-                ui.horizontal(|ui| {
-                    *update_lights |= ui.add(Slider::new(saturation, 0.0..=1.0)).changed();
-                    ui.label("Saturation Boost");
-                });
-            } else {
-                let range = if profile.effect.is_built_in() { SPEED_RANGE } else { 1..=10 };
+            let mut shown_sliders = 0;
+
+            for parameter in effect.info().parameters {
+                match (parameter, &mut profile.effect) {
+                    (Parameter::Speed(range), _) => {
+                        ui.horizontal(|ui| {
+                            *update_lights |= ui.add(Slider::new(&mut profile.speed, range.clone())).changed();
+                            ui.label("Speed");
+                        });
+                    }
+                    (Parameter::Fps(range), Effects::AmbientLight { fps, .. }) => {
+                        ui.horizontal(|ui| {
+                            *update_lights |= ui.add(Slider::new(fps, range.clone())).changed();
+                            ui.label("FPS");
+                        });
+                    }
+                    (Parameter::SaturationBoost, Effects::AmbientLight { saturation_boost, .. }) => {
+                        ui.horizontal(|ui| {
+                            *update_lights |= ui.add(Slider::new(saturation_boost, 0.0..=1.0)).changed();
+                            ui.label("Saturation Boost");
+                        });
+                    }
+                    (Parameter::VisualizerMode, Effects::Visualizer { mode, .. }) => {
+                        ComboBox::from_label("Mode")
+                            .width(COMBOBOX_WIDTH)
+                            .selected_text({
+                                let text: &'static str = (*mode).into();
+                                text
+                            })
+                            .show_ui(ui, |ui| {
+                                for val in VisualizerMode::iter() {
+                                    let text: &'static str = val.into();
+                                    *update_lights |= ui.selectable_value(mode, val, text).changed();
+                                }
+                            });
+                    }
+                    (Parameter::Sensitivity, Effects::Visualizer { sensitivity, .. }) => {
+                        ui.horizontal(|ui| {
+                            *update_lights |= ui.add(Slider::new(sensitivity, SENSITIVITY_RANGE)).changed();
+                            ui.label("Sensitivity");
+                        });
+                    }
+                    (Parameter::Smoothing, Effects::Visualizer { smoothing, .. }) => {
+                        ui.horizontal(|ui| {
+                            *update_lights |= ui.add(Slider::new(smoothing, SMOOTHING_RANGE)).changed();
+                            ui.label("Smoothing");
+                        });
+                    }
+                    (Parameter::Palette, Effects::Visualizer { palette, .. }) => {
+                        ComboBox::from_label("Palette")
+                            .width(COMBOBOX_WIDTH)
+                            .selected_text({
+                                let text: &'static str = (*palette).into();
+                                text
+                            })
+                            .show_ui(ui, |ui| {
+                                for val in Palette::iter() {
+                                    let text: &'static str = val.into();
+                                    *update_lights |= ui.selectable_value(palette, val, text).changed();
+                                }
+                            });
+                    }
+                    // Shown elsewhere
+                    _ => continue,
+                }
+
+                shown_sliders += 1;
+            }
 
+            // Keep the layout from jumping around between effects
+            if shown_sliders == 0 {
                 ui.horizontal(|ui| {
-                    *update_lights |= ui.add_enabled(profile.effect.takes_speed(), Slider::new(&mut profile.speed, range)).changed();
+                    ui.add_enabled(false, Slider::new(&mut profile.speed, SOFTWARE_SPEED_RANGE));
                     ui.label("Speed");
                 });
             }
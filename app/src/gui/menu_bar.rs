@@ -7,7 +7,7 @@ use egui_file::FileDialog;
 use egui_notify::Toasts;
 use std::{path::PathBuf, time::Duration};
 
-use crate::{effects::custom_effect::CustomEffect, gui::modals, profile::Profile};
+use crate::{diagnostics, effects::custom_effect::CustomEffect, enums::ExitBehavior, gui::modals, profile::Profile};
 
 use super::{CustomEffectState, GuiMessage};
 
@@ -38,10 +38,13 @@ enum FileOperation {
 }
 
 impl MenuBarState {
-    pub fn show(&mut self, ctx: &Context, ui: &mut egui::Ui, current_profile: &mut Profile, current_effect: &mut CustomEffectState, changed: &mut bool) {
+    /// `can_restore` tells whether the keyboard's startup state is known, which restoring it on exit needs
+    pub fn show(
+        &mut self, ctx: &Context, ui: &mut egui::Ui, current_profile: &mut Profile, exit_behavior: &mut ExitBehavior, can_restore: bool, current_effect: &mut CustomEffectState, changed: &mut bool,
+    ) {
         self.toasts.show(ctx);
 
-        self.show_menu(ctx, ui);
+        self.show_menu(ctx, ui, current_profile, exit_behavior, can_restore);
 
         if let Some(dialog) = &mut self.open_file_dialog {
             if dialog.show(ctx).selected() {
@@ -54,8 +57,11 @@ impl MenuBarState {
                                     *current_profile = profile;
                                     *changed = true;
                                 }
-                                Err(_) => {
-                                    self.toasts.error("Could not load profile.").set_duration(Some(Duration::from_millis(5000))).set_closable(true);
+                                Err(err) => {
+                                    self.toasts
+                                        .error(format!("Could not load profile: {}", diagnostics::summary(&err)))
+                                        .set_duration(Some(Duration::from_millis(10000)))
+                                        .set_closable(true);
                                 }
                             },
                             FileOperation::LoadEffect => match CustomEffect::from_file(path) {
@@ -63,8 +69,11 @@ impl MenuBarState {
                                     *current_effect = CustomEffectState::Queued(effect);
                                     *changed = true;
                                 }
-                                Err(_) => {
-                                    self.toasts.error("Could not load custom effect.").set_duration(Some(Duration::from_millis(5000))).set_closable(true);
+                                Err(err) => {
+                                    self.toasts
+                                        .error(format!("Could not load custom effect: {}", diagnostics::summary(&err)))
+                                        .set_duration(Some(Duration::from_millis(10000)))
+                                        .set_closable(true);
                                 }
                             },
                             FileOperation::SaveProfile => {
@@ -81,7 +90,11 @@ impl MenuBarState {
         }
     }
 
-    fn show_menu(&mut self, ctx: &Context, ui: &mut egui::Ui) {
+    pub fn show_error(&mut self, text: String) {
+        self.toasts.error(text).set_duration(Some(Duration::from_millis(5000))).set_closable(true);
+    }
+
+    fn show_menu(&mut self, ctx: &Context, ui: &mut egui::Ui, current_profile: &Profile, exit_behavior: &mut ExitBehavior, can_restore: bool) {
         use egui::menu;
 
         menu::bar(ui, |ui| {
@@ -109,6 +122,30 @@ impl MenuBarState {
                 }
             });
 
+            ui.menu_button("On exit", |ui| {
+                if ui.radio(*exit_behavior == ExitBehavior::Keep, "Keep the last frame").clicked() {
+                    *exit_behavior = ExitBehavior::Keep;
+                }
+                if ui
+                    .add_enabled(can_restore, egui::RadioButton::new(*exit_behavior == ExitBehavior::Restore, "Restore the startup state"))
+                    .on_disabled_hover_text("This keyboard can't report what it was showing on startup, so the last frame is kept instead")
+                    .clicked()
+                {
+                    *exit_behavior = ExitBehavior::Restore;
+                }
+                if ui.radio(*exit_behavior == ExitBehavior::Off, "Turn off").clicked() {
+                    *exit_behavior = ExitBehavior::Off;
+                }
+
+                let text = match exit_behavior {
+                    ExitBehavior::Profile(profile) => format!("Switch to \"{}\"", profile.name),
+                    _ => "Switch to the current profile".to_string(),
+                };
+                if ui.radio(matches!(exit_behavior, ExitBehavior::Profile(_)), text).clicked() {
+                    *exit_behavior = ExitBehavior::Profile(current_profile.clone());
+                }
+            });
+
             let about_modal = modals::about(ctx);
             if ui.button("About").clicked() {
                 about_modal.open();
@@ -11,14 +11,15 @@ use eframe::{
     CreationContext,
 };
 
-use strum::IntoEnumIterator;
+use legion_rgb_driver::{device::DeviceSelector, error::Error as DriverError};
 use tray_item::{IconSource, TrayItem};
 
 use crate::{
     cli::OutputType,
-    effects::{self, custom_effect::CustomEffect, EffectManager, ManagerCreationError},
-    enums::Effects,
+    effects::{self, custom_effect::CustomEffect, registry, EffectManager, ManagerCreationError},
+    enums::ExitBehavior,
     persist::Settings,
+    profile::Profile,
 };
 
 use self::{effect_options::EffectOptions, menu_bar::MenuBarState, profile_list::ProfileList, style::Theme};
@@ -33,6 +34,8 @@ pub struct App {
     settings: Settings,
 
     instance_not_unique: bool,
+    /// The path of the keyboard and the udev rule needed to access it
+    permission_denied: Option<(String, String)>,
     hide_window: bool,
     window_open_rx: Option<crossbeam_channel::Receiver<GuiMessage>>,
     // The tray struct needs to be kept from being dropped for the tray to appear on windows
@@ -65,8 +68,8 @@ pub enum CustomEffectState {
 }
 
 impl App {
-    pub fn new(output: OutputType, hide_window: bool, tx: Sender<GuiMessage>, rx: Receiver<GuiMessage>) -> Self {
-        let manager_result = EffectManager::new(effects::OperationMode::Gui);
+    pub fn new(output: OutputType, hide_window: bool, device: Option<&DeviceSelector>, exit_behavior: Option<ExitBehavior>, tx: Sender<GuiMessage>, rx: Receiver<GuiMessage>) -> Self {
+        let manager_result = EffectManager::new(effects::OperationMode::Gui, device);
 
         let instance_not_unique = if let Err(err) = &manager_result {
             &ManagerCreationError::InstanceAlreadyRunning == err.current_context()
@@ -74,16 +77,38 @@ impl App {
             false
         };
 
+        let permission_denied = match manager_result.as_ref().err().and_then(|err| err.downcast_ref::<DriverError>()) {
+            Some(DriverError::PermissionDenied { path, udev_rule }) => Some((path.clone(), udev_rule.clone())),
+            _ => None,
+        };
+
         let manager = manager_result.ok();
 
-        let settings: Settings = Settings::load();
+        let is_first_launch = !Settings::exists();
+        let mut settings: Settings = Settings::load();
         let profiles = settings.profiles.clone();
 
+        if let Some(exit_behavior) = exit_behavior {
+            settings.exit_behavior = exit_behavior;
+        }
+
+        // Without any saved state, start off from whatever the keyboard is already displaying
+        if is_first_launch {
+            if let Some(state) = manager.as_ref().and_then(EffectManager::hardware_state) {
+                settings.current_profile = Profile::from(state);
+            }
+        }
+
+        if let Some(manager) = &manager {
+            manager.set_exit_behavior(settings.exit_behavior.clone());
+        }
+
         // Default app state
         let mut app = Self {
             settings,
 
             instance_not_unique,
+            permission_denied,
             hide_window,
             window_open_rx: Some(rx),
             tray: None,
@@ -105,13 +130,13 @@ impl App {
             OutputType::Profile(profile) => app.settings.current_profile = profile,
             OutputType::Custom(effect) => app.custom_effect = CustomEffectState::Queued(effect),
             OutputType::NoArgs => {}
-            OutputType::Exit => unreachable!("Exiting the app supersedes starting the GUI"),
+            OutputType::Exit(_) => unreachable!("Exiting the app supersedes starting the GUI"),
         }
 
         app
     }
 
-    pub fn init(mut self, cc: &CreationContext<'_>, gui_sender: Sender<GuiMessage>) -> Self {
+    pub fn init(mut self, cc: &CreationContext<'_>, gui_sender: Sender<GuiMessage>, termination_rx: Receiver<()>) -> Self {
         //Create the tray icon
         #[cfg(target_os = "linux")]
         let tray_icon = load_tray_icon(include_bytes!("../../res/trayIcon.ico"));
@@ -152,6 +177,16 @@ impl App {
             None
         };
 
+        // Quit the same way as from the tray when killed
+        let quit_sender = gui_sender.clone();
+        let egui_ctx = cc.egui_ctx.clone();
+        thread::spawn(move || {
+            if termination_rx.recv().is_ok() {
+                let _ = quit_sender.send(GuiMessage::Quit);
+                egui_ctx.request_repaint();
+            }
+        });
+
         let ctx = cc.egui_ctx.clone();
         if self.manager.is_some() {
             thread::spawn(move || {
@@ -194,19 +229,42 @@ impl eframe::App for App {
             }
         }
 
+        if let Some(manager) = &self.manager {
+            for err in manager.errors() {
+                self.menu_bar.show_error(err.to_string());
+            }
+        }
+
         if self.instance_not_unique && modals::unique_instance(ctx) {
             self.exit_app();
         };
 
         // The uniqueness prompt has priority over generic errors
-        if !self.instance_not_unique && self.manager.is_none() && modals::manager_error(ctx) {
+        if !self.instance_not_unique && self.manager.is_none() && modals::manager_error(ctx, self.permission_denied.as_ref()) {
             self.exit_app();
         };
 
         frame.set_visible(!self.hide_window);
 
         TopBottomPanel::top("top-panel").show(ctx, |ui| {
-            self.menu_bar.show(ctx, ui, &mut self.settings.current_profile, &mut self.custom_effect, &mut self.profile_changed);
+            let exit_behavior = self.settings.exit_behavior.clone();
+            let can_restore = self.manager.as_ref().is_some_and(|manager| manager.hardware_state().is_some());
+
+            self.menu_bar.show(
+                ctx,
+                ui,
+                &mut self.settings.current_profile,
+                &mut self.settings.exit_behavior,
+                can_restore,
+                &mut self.custom_effect,
+                &mut self.profile_changed,
+            );
+
+            if self.settings.exit_behavior != exit_behavior {
+                if let Some(manager) = &self.manager {
+                    manager.set_exit_behavior(self.settings.exit_behavior.clone());
+                }
+            }
         });
 
         CentralPanel::default()
@@ -269,9 +327,9 @@ impl eframe::App for App {
 
                             ScrollArea::vertical().show(ui, |ui| {
                                 ui.with_layout(Layout::top_down_justified(Align::Min), |ui| {
-                                    for val in Effects::iter() {
-                                        let text: &'static str = val.into();
-                                        if ui.selectable_value(&mut self.settings.current_profile.effect, val, text).clicked() {
+                                    for effect in registry::EFFECTS {
+                                        let response = ui.selectable_value(&mut self.settings.current_profile.effect, effect.kind(), effect.name());
+                                        if response.on_hover_text(effect.info().description).clicked() {
                                             self.profile_changed = true;
                                             self.custom_effect = CustomEffectState::None;
                                         };
@@ -312,6 +370,10 @@ impl eframe::App for App {
         self.settings.profiles = std::mem::take(&mut self.profile_list.profiles);
 
         self.settings.save();
+
+        if let Some(manager) = self.manager.as_mut() {
+            manager.shutdown();
+        }
     }
 }
 
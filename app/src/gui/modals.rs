@@ -1,4 +1,4 @@
-use eframe::egui::{Color32, Context, Frame, ScrollArea};
+use eframe::egui::{Color32, Context, Frame, Label, RichText, ScrollArea, Ui};
 use egui_modal::Modal;
 
 use crate::util;
@@ -24,7 +24,8 @@ pub fn unique_instance(ctx: &Context) -> bool {
     exit_app
 }
 
-pub fn manager_error(ctx: &Context) -> bool {
+/// `permission_denied` holds the path of a keyboard that was found but couldn't be opened and the udev rule that would allow it
+pub fn manager_error(ctx: &Context, permission_denied: Option<&(String, String)>) -> bool {
     let mut exit_app = false;
 
     let modal = Modal::new(ctx, "manager_error_modal");
@@ -32,6 +33,16 @@ pub fn manager_error(ctx: &Context) -> bool {
     modal.show(|ui| {
         modal.title(ui, "Warning");
         modal.frame(ui, |ui| {
+            if let Some((path, udev_rule)) = permission_denied {
+                modal.body(ui, format!("A supported keyboard was found at {path}, but the application is not allowed to access it."));
+                modal.body(ui, "Add the following udev rule (e.g. in /etc/udev/rules.d/99-kblight.rules):");
+                code_block(ui, udev_rule);
+                modal.body(ui, "And then reload the rules:");
+                code_block(ui, "sudo udevadm control --reload-rules && sudo udevadm trigger");
+
+                return;
+            }
+
             modal.body(ui, "Failed to find a valid keyboard.");
             modal.body(ui, "Ensure that you have a supported model and that the application has access to it.");
             ui.horizontal(|ui| {
@@ -68,6 +79,12 @@ pub fn manager_error(ctx: &Context) -> bool {
     exit_app
 }
 
+fn code_block(ui: &mut Ui, text: &str) {
+    Frame::none().fill(Color32::from_gray(20)).inner_margin(5.0).rounding(6.0).show(ui, |ui| {
+        ui.add(Label::new(RichText::new(text).monospace()).wrap(true));
+    });
+}
+
 pub fn about(ctx: &Context) -> Modal {
     let modal = Modal::new(ctx, "about_modal");
 
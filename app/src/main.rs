@@ -1,44 +1,54 @@
 #![cfg_attr(not(test), windows_subsystem = "windows")]
 #![cfg_attr(test, windows_subsystem = "console")]
 
+mod audio;
 mod cli;
 #[cfg(target_os = "windows")]
 mod console;
+mod diagnostics;
 mod effects;
 mod enums;
 mod gui;
 mod persist;
 mod profile;
+#[cfg(target_os = "linux")]
+mod udev;
 mod util;
 
 use cli::{GuiCommand, OutputType};
 use color_eyre::{eyre::eyre, Result};
+use crossbeam_channel::Receiver;
 use eframe::{epaint::Vec2, IconData};
+use enums::ExitBehavior;
 use gui::{App, GuiMessage};
+use legion_rgb_driver::device::DeviceSelector;
+use std::process::ExitCode;
 
 const WINDOW_SIZE: Vec2 = Vec2::new(500., 400.);
 
-fn main() {
-    #[cfg(target_os = "windows")]
-    {
-        setup_panic().unwrap();
+#[cfg(target_os = "windows")]
+fn main() -> ExitCode {
+    setup_panic().unwrap();
 
-        // This just enables output if the program is already being ran from the CLI
-        console::attach();
-        let res = init();
-        console::free();
+    // This just enables output if the program is already being ran from the CLI
+    console::attach();
+    let res = init();
+    console::free();
 
-        if res.is_err() {
-            std::process::exit(2);
-        }
-    }
+    res.unwrap_or(ExitCode::from(2))
+}
 
-    #[cfg(target_os = "linux")]
-    {
-        color_eyre::install().unwrap();
+#[cfg(target_os = "linux")]
+fn main() -> ExitCode {
+    color_eyre::install().unwrap();
 
-        init().unwrap();
-    }
+    init().unwrap()
+}
+
+/// Only Windows and Linux are supported, other platforms are built by CI but have nothing to run
+#[cfg(not(any(target_os = "windows", target_os = "linux")))]
+fn main() -> ExitCode {
+    ExitCode::SUCCESS
 }
 
 #[cfg(target_os = "windows")]
@@ -68,20 +78,48 @@ fn setup_panic() -> Result<()> {
     Ok(())
 }
 
-fn init() -> Result<()> {
-    let cli_output = cli::try_cli().map_err(|err| eyre!("{:?}", err))?;
+/// Run whatever was asked for, the CLI decides what status to leave with
+fn init() -> Result<ExitCode> {
+    let termination_rx = listen_for_termination();
+
+    let cli_output = cli::try_cli(&termination_rx).map_err(|err| eyre!("{:?}", err))?;
 
     match cli_output {
-        GuiCommand::Start { hide_window, output } => {
-            start_ui(output, hide_window);
+        GuiCommand::Start {
+            hide_window,
+            device,
+            exit_behavior,
+            output,
+        } => {
+            start_ui(output, hide_window, device, exit_behavior, termination_rx);
+
+            Ok(ExitCode::SUCCESS)
+        }
+        GuiCommand::Exit(code) => Ok(code),
+    }
+}
 
-            Ok(())
+/// Forward termination signals (Ctrl+C, SIGTERM...) to whoever is waiting on the returned receiver, so that the exit behavior gets applied
+///
+/// When nothing is (e.g. a command that doesn't touch the keyboard, or a second Ctrl+C while shutting down) the process exits right away
+fn listen_for_termination() -> Receiver<()> {
+    let (termination_tx, termination_rx) = crossbeam_channel::bounded(0);
+
+    let handler_result = ctrlc::set_handler(move || {
+        if termination_tx.try_send(()).is_err() {
+            // The usual exit code after being interrupted
+            std::process::exit(130);
         }
-        GuiCommand::Exit => Ok(()),
+    });
+
+    if let Err(err) = handler_result {
+        eprintln!("Could not listen for termination signals: {err}");
     }
+
+    termination_rx
 }
 
-fn start_ui(output_type: OutputType, hide_window: bool) {
+fn start_ui(output_type: OutputType, hide_window: bool, device: Option<DeviceSelector>, exit_behavior: Option<ExitBehavior>, termination_rx: Receiver<()>) {
     let app_icon = load_icon_data(include_bytes!("../res/trayIcon.ico"));
     let native_options = eframe::NativeOptions {
         initial_window_size: Some(WINDOW_SIZE),
@@ -94,9 +132,9 @@ fn start_ui(output_type: OutputType, hide_window: bool) {
     let (gui_sender, gui_receiver) = crossbeam_channel::unbounded::<GuiMessage>();
 
     let gui_sender_clone = gui_sender.clone();
-    let app = App::new(output_type, hide_window, gui_sender_clone, gui_receiver);
+    let app = App::new(output_type, hide_window, device.as_ref(), exit_behavior, gui_sender_clone, gui_receiver);
 
-    eframe::run_native("Legion RGB", native_options, Box::new(|cc| Box::new(app.init(cc, gui_sender)))).unwrap();
+    eframe::run_native("Legion RGB", native_options, Box::new(|cc| Box::new(app.init(cc, gui_sender, termination_rx)))).unwrap();
 }
 
 #[must_use]
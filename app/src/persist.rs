@@ -5,7 +5,7 @@ use std::{
     path::PathBuf,
 };
 
-use crate::profile::Profile;
+use crate::{enums::ExitBehavior, profile::Profile};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Deserialize, Serialize, Default)]
@@ -14,6 +14,8 @@ pub struct Settings {
     // Up to 0.19.5
     #[serde(alias = "ui_state")]
     pub current_profile: Profile,
+    #[serde(default)]
+    pub exit_behavior: ExitBehavior,
 }
 
 impl Settings {
@@ -28,6 +30,11 @@ impl Settings {
         persist
     }
 
+    /// Whether a settings file is present at the configured path
+    pub fn exists() -> bool {
+        Self::get_location().exists()
+    }
+
     /// Save the settings to the configured path
     pub fn save(&mut self) {
         let mut file = File::create(Self::get_location()).unwrap();
@@ -1,11 +1,17 @@
 use std::{convert::TryInto, path::Path};
 
 use crate::{
-    enums::{Brightness, Direction, Effects},
+    diagnostics::{self, Diagnostic},
+    effects::registry,
+    enums::{BlendMode, Brightness, Direction, Effects},
     util::StorageTrait,
 };
 
 use error_stack::{Result, ResultExt};
+use legion_rgb_driver::{
+    color::{Rgb, ZoneColors},
+    BaseEffects, LightingState,
+};
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -34,6 +40,55 @@ pub struct Profile {
     pub direction: Direction,
     pub speed: u8,
     pub brightness: Brightness,
+    /// Percentage the colors are scaled down to on top of the hardware brightness
+    #[serde(default = "default_software_brightness")]
+    pub software_brightness: u8,
+    /// Effects drawn on top of the main one, from the bottom up
+    #[serde(default)]
+    pub layers: Vec<Layer>,
+}
+
+fn default_software_brightness() -> u8 {
+    100
+}
+
+/// An effect drawn over the ones below it
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Layer {
+    pub effect: Effects,
+    #[serde(default)]
+    pub rgb_zones: Zones,
+    #[serde(default)]
+    pub direction: Direction,
+    #[serde(default = "default_layer_speed")]
+    pub speed: u8,
+    #[serde(default)]
+    pub blend_mode: BlendMode,
+    /// How much the layer shows through, from 0 to 1
+    #[serde(default = "default_layer_opacity")]
+    pub opacity: f32,
+}
+
+fn default_layer_speed() -> u8 {
+    1
+}
+
+fn default_layer_opacity() -> f32 {
+    1.0
+}
+
+impl Layer {
+    /// The settings the layer's effect is started with, the rest is taken from the profile it is part of
+    pub fn to_profile(&self, base: &Profile) -> Profile {
+        Profile {
+            rgb_zones: self.rgb_zones,
+            effect: self.effect,
+            direction: self.direction,
+            speed: self.speed,
+            layers: Vec::new(),
+            ..base.clone()
+        }
+    }
 }
 
 impl Default for Profile {
@@ -45,6 +100,31 @@ impl Default for Profile {
             direction: Direction::default(),
             speed: 1,
             brightness: Brightness::default(),
+            software_brightness: default_software_brightness(),
+            layers: Vec::new(),
+        }
+    }
+}
+
+impl From<&LightingState> for Profile {
+    fn from(state: &LightingState) -> Self {
+        let (effect, direction) = match state.effect_type() {
+            BaseEffects::Static => (Effects::Static, Direction::default()),
+            BaseEffects::Breath => (Effects::Breath, Direction::default()),
+            BaseEffects::Smooth => (Effects::Smooth, Direction::default()),
+            BaseEffects::LeftWave => (Effects::Wave, Direction::Left),
+            BaseEffects::RightWave => (Effects::Wave, Direction::Right),
+        };
+
+        let brightness = if state.brightness() > 1 { Brightness::High } else { Brightness::Low };
+
+        Self {
+            rgb_zones: arr_to_zones(state.rgb_values()),
+            effect,
+            direction,
+            speed: state.speed(),
+            brightness,
+            ..Default::default()
         }
     }
 }
@@ -59,7 +139,53 @@ pub struct SaveProfileError;
 
 impl Profile {
     pub fn load_profile(path: &Path) -> Result<Self, LoadProfileError> {
-        Self::load(path).change_context(LoadProfileError)
+        let profile = Self::load(path).change_context(LoadProfileError)?;
+
+        let problems = profile.validate();
+        if !problems.is_empty() {
+            return Err(diagnostics::report(LoadProfileError, problems.into_iter().map(|diagnostic| diagnostic.in_file(path)).collect()));
+        }
+
+        Ok(profile)
+    }
+
+    /// The values that are readable but out of range
+    pub fn validate(&self) -> Vec<Diagnostic> {
+        let mut problems = Vec::new();
+
+        if let Some(speed) = check_speed(self.effect, self.speed) {
+            problems.push(Diagnostic::at_field("speed", speed));
+        }
+
+        if self.software_brightness > 100 {
+            problems.push(Diagnostic::at_field("software_brightness", format!("must be at most 100, got {}", self.software_brightness)));
+        }
+
+        if !self.layers.is_empty() && !registry::find(self.effect).can_be_layered() {
+            problems.push(Diagnostic::at_field(
+                "effect",
+                format!("{} is played by the keyboard itself, so nothing can be layered on top of it", registry::find(self.effect).name()),
+            ));
+        }
+
+        for (i, layer) in self.layers.iter().enumerate() {
+            if !registry::find(layer.effect).can_be_layered() {
+                problems.push(Diagnostic::at_field(
+                    format!("layers[{i}].effect"),
+                    format!("{} is played by the keyboard itself and can't be layered", registry::find(layer.effect).name()),
+                ));
+            }
+
+            if let Some(speed) = check_speed(layer.effect, layer.speed) {
+                problems.push(Diagnostic::at_field(format!("layers[{i}].speed"), speed));
+            }
+
+            if !(0.0..=1.0).contains(&layer.opacity) {
+                problems.push(Diagnostic::at_field(format!("layers[{i}].opacity"), format!("must be between 0 and 1, got {}", layer.opacity)));
+            }
+        }
+
+        problems
     }
 
     pub fn save_profile(&self, path: &Path) -> Result<(), SaveProfileError> {
@@ -69,6 +195,17 @@ impl Profile {
     pub fn rgb_array(&self) -> [u8; 12] {
         self.rgb_zones.map(|zone| if zone.enabled { zone.rgb } else { [0; 3] }).concat().try_into().unwrap()
     }
+
+    pub fn zone_colors(&self) -> ZoneColors {
+        ZoneColors(self.rgb_zones.map(|zone| if zone.enabled { Rgb::from(zone.rgb) } else { Rgb::BLACK }))
+    }
+}
+
+fn check_speed(effect: Effects, speed: u8) -> Option<String> {
+    let effect = registry::find(effect);
+    let range = effect.speed_range()?;
+
+    (!range.contains(&speed)).then(|| format!("{} takes a speed from {} to {}, got {speed}", effect.name(), range.start(), range.end()))
 }
 
 pub fn arr_to_zones(arr: [u8; 12]) -> Zones {
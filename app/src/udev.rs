@@ -0,0 +1,67 @@
+use std::{fs, path::Path, process::Command};
+
+use error_stack::{Report, Result, ResultExt};
+use legion_rgb_driver::device::{self, DeviceSelector};
+use thiserror::Error;
+
+pub const DEFAULT_RULES_PATH: &str = "/etc/udev/rules.d/99-kblight.rules";
+
+#[derive(Debug, Error)]
+#[error("Could not set up the udev rules")]
+pub struct UdevError;
+
+/// Build the rules file for the connected keyboards (optionally narrowed down by a selector), or for every known model
+pub fn generate_rules(all: bool, selector: Option<&DeviceSelector>) -> Result<String, UdevError> {
+    let mut models: Vec<(u16, u16, String)> = Vec::new();
+
+    if all {
+        models.extend(device::database().devices().iter().map(|d| (d.vendor_id, d.product_id, d.model.clone())));
+    } else {
+        let keyboards = device::list_keyboards().change_context(UdevError)?;
+
+        for keyboard in keyboards {
+            if selector.is_some_and(|selector| !selector.matches(&keyboard)) {
+                continue;
+            }
+
+            models.push((keyboard.vendor_id, keyboard.product_id, keyboard.model));
+        }
+
+        if models.is_empty() {
+            return Err(Report::new(UdevError)
+                .attach_printable("No supported keyboard is connected")
+                .attach_printable("Use --all to generate the rules for every known model"));
+        }
+    }
+
+    // The same model may be listed more than once (e.g. several interfaces, or a user entry overriding a built-in one)
+    models.sort_by_key(|(vendor_id, product_id, _)| (*vendor_id, *product_id));
+    models.dedup_by_key(|(vendor_id, product_id, _)| (*vendor_id, *product_id));
+
+    let mut rules = format!("# Generated by {} {}\n", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
+
+    for (vendor_id, product_id, model) in models {
+        rules.push_str(&format!("# {model}\n{}\n", device::udev_rule(vendor_id, product_id)));
+    }
+
+    Ok(rules)
+}
+
+pub fn write_rules(rules: &str, path: &Path) -> Result<(), UdevError> {
+    fs::write(path, rules)
+        .change_context(UdevError)
+        .attach_printable_lazy(|| format!("Could not write to {}, writing to /etc usually requires running with sudo", path.display()))
+}
+
+/// Have udev pick up the new rules and apply them to the devices already plugged in
+pub fn reload_rules() -> Result<(), UdevError> {
+    for args in [["control", "--reload-rules"].as_slice(), ["trigger"].as_slice()] {
+        let status = Command::new("udevadm").args(args).status().change_context(UdevError).attach_printable("Could not run udevadm")?;
+
+        if !status.success() {
+            return Err(Report::new(UdevError).attach_printable(format!("udevadm {} exited with {status}", args.join(" "))));
+        }
+    }
+
+    Ok(())
+}
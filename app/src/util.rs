@@ -1,9 +1,11 @@
 use eframe::egui::Ui;
-use error_stack::{Result, ResultExt};
+use error_stack::{Report, Result, ResultExt};
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use std::{fs::File, io::Write, path::Path};
 use thiserror::Error;
 
+use crate::diagnostics::Diagnostic;
+
 #[derive(Debug, Error)]
 #[error("Failed to load file")]
 pub struct LoadFileError;
@@ -17,12 +19,17 @@ where
     Self: DeserializeOwned + Serialize + Sized,
     for<'de> Self: Deserialize<'de> + 'a,
 {
+    /// Errors carry a [`Diagnostic`] pointing at where the file went wrong
     fn load(path: &Path) -> Result<Self, LoadFileError> {
-        let file = std::fs::File::open(path).change_context(LoadFileError)?;
-
-        let reader = std::io::BufReader::new(file);
-
-        serde_json::de::from_reader(reader).change_context(LoadFileError)
+        let json = std::fs::read_to_string(path).map_err(|err| {
+            let diagnostic = Diagnostic::new(err.to_string()).in_file(path);
+            Report::new(err).change_context(LoadFileError).attach_printable(diagnostic)
+        })?;
+
+        serde_json::from_str(&json).map_err(|err| {
+            let diagnostic = Diagnostic::from_json_error(&err).in_file(path);
+            Report::new(err).change_context(LoadFileError).attach_printable(diagnostic)
+        })
     }
 
     fn save(&self, path: &Path) -> Result<(), SaveFileError> {
@@ -0,0 +1,166 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::{BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use crate::{
+    error::{Error, Result},
+    transport::PAYLOAD_SIZE,
+};
+
+/// How long recorded payloads can sit in the buffer, so that a crash loses at most this much of the capture
+const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Appends every payload it is given to a capture file
+///
+/// Each line holds the milliseconds since the unix epoch followed by the payload bytes in hex, e.g. `1700000000000 cc 16 01 ...`
+///
+/// Writes are buffered and flushed every [`FLUSH_INTERVAL`] at most, as well as when the recorder is dropped
+pub struct PayloadRecorder {
+    writer: BufWriter<File>,
+    last_flush: Instant,
+}
+
+impl PayloadRecorder {
+    /// Open a capture file for appending, creating it if needed
+    pub fn create(path: &Path) -> Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(Self {
+            writer: BufWriter::new(file),
+            last_flush: Instant::now(),
+        })
+    }
+
+    pub fn record(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_millis();
+        let bytes = payload.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<String>>().join(" ");
+
+        writeln!(self.writer, "{timestamp} {bytes}")?;
+
+        if self.last_flush.elapsed() >= FLUSH_INTERVAL {
+            self.flush()?;
+        }
+
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.writer.flush()?;
+        self.last_flush = Instant::now();
+
+        Ok(())
+    }
+}
+
+impl Drop for PayloadRecorder {
+    fn drop(&mut self) {
+        // Nowhere to report a failure to at this point, same as the writer's own drop
+        let _ = self.flush();
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CapturedPayload {
+    /// Time since the unix epoch
+    pub timestamp: Duration,
+    pub payload: [u8; PAYLOAD_SIZE],
+}
+
+/// Read back a file written by a [`PayloadRecorder`], blank lines and lines starting with `#` are skipped
+pub fn read_capture(path: &Path) -> Result<Vec<CapturedPayload>> {
+    let reader = BufReader::new(File::open(path)?);
+
+    let mut captured = Vec::new();
+
+    for (index, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let parsed = parse_line(line).ok_or(Error::InvalidCapture { line: index + 1 })?;
+        captured.push(parsed);
+    }
+
+    Ok(captured)
+}
+
+fn parse_line(line: &str) -> Option<CapturedPayload> {
+    let mut fields = line.split_whitespace();
+
+    let timestamp = Duration::from_millis(fields.next()?.parse().ok()?);
+
+    let mut payload = [0; PAYLOAD_SIZE];
+    for byte in &mut payload {
+        *byte = u8::from_str_radix(fields.next()?, 16).ok()?;
+    }
+
+    if fields.next().is_some() {
+        return None;
+    }
+
+    Some(CapturedPayload { timestamp, payload })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use super::*;
+
+    fn payload(effect: u8) -> [u8; PAYLOAD_SIZE] {
+        let mut payload = [0; PAYLOAD_SIZE];
+        payload[..5].copy_from_slice(&[0xcc, 0x16, effect, 0x02, 0x01]);
+        payload[5..8].copy_from_slice(&[0xff, 0x80, 0x00]);
+
+        payload
+    }
+
+    #[test]
+    fn recorded_payloads_read_back() {
+        let path = env::temp_dir().join(format!("legion-rgb-capture-{}.txt", process::id()));
+        let _ = fs::remove_file(&path);
+
+        {
+            let mut recorder = PayloadRecorder::create(&path).unwrap();
+            recorder.record(&payload(0x01)).unwrap();
+            recorder.record(&payload(0x03)).unwrap();
+        }
+
+        let captured = read_capture(&path);
+        fs::remove_file(&path).unwrap();
+        let captured = captured.unwrap();
+
+        assert_eq!(captured.iter().map(|captured| captured.payload).collect::<Vec<_>>(), [payload(0x01), payload(0x03)]);
+        assert!(captured[0].timestamp <= captured[1].timestamp);
+    }
+
+    #[test]
+    fn lines_are_parsed() {
+        let line = format!("1700000000000 {}", payload(0x06).map(|byte| format!("{byte:02x}")).join(" "));
+
+        assert_eq!(
+            parse_line(&line),
+            Some(CapturedPayload {
+                timestamp: Duration::from_millis(1_700_000_000_000),
+                payload: payload(0x06),
+            })
+        );
+    }
+
+    #[test]
+    fn malformed_lines_are_rejected() {
+        let bytes = payload(0x01).map(|byte| format!("{byte:02x}")).join(" ");
+
+        assert_eq!(parse_line(""), None);
+        assert_eq!(parse_line(&format!("yesterday {bytes}")), None);
+        assert_eq!(parse_line(&format!("1 {bytes} 00")), None);
+        assert_eq!(parse_line(&format!("1 {}", &bytes[..bytes.len() - 3])), None);
+        assert_eq!(parse_line(&format!("1 zz{}", &bytes[2..])), None);
+    }
+}
@@ -0,0 +1,344 @@
+use std::{fmt, fs, path::Path, str::FromStr, sync::OnceLock};
+
+use hidapi::{DeviceInfo, HidApi};
+use serde::{Deserialize, Deserializer};
+
+use crate::error::Result;
+
+const BUILTIN_DEVICES: &str = include_str!("../res/devices.json");
+
+static DATABASE: OnceLock<DeviceDatabase> = OnceLock::new();
+
+/// Something a keyboard model is known to support on top of setting static colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Deserialize)]
+pub enum Capability {
+    /// The breath, smooth and wave effects run by the controller itself
+    HardwareEffects,
+    /// The controller reports what it is currently displaying, only flag the models it was checked on as others may answer with garbage
+    Readback,
+}
+
+impl Capability {
+    pub const ALL: [Self; 2] = [Self::HardwareEffects, Self::Readback];
+}
+
+/// A keyboard model as described in the device database
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+pub struct DeviceDefinition {
+    #[serde(deserialize_with = "deserialize_hex_id")]
+    pub vendor_id: u16,
+    #[serde(deserialize_with = "deserialize_hex_id")]
+    pub product_id: u16,
+    /// Only needed to pick the right interface on windows
+    #[serde(deserialize_with = "deserialize_hex_id", default = "default_usage_page")]
+    pub usage_page: u16,
+    #[serde(deserialize_with = "deserialize_hex_id", default = "default_usage")]
+    pub usage: u16,
+    pub model: String,
+    #[serde(default = "default_zones")]
+    pub zones: u8,
+    #[serde(default = "default_capabilities")]
+    pub capabilities: Vec<Capability>,
+}
+
+fn default_usage_page() -> u16 {
+    0xff89
+}
+
+fn default_usage() -> u16 {
+    0x00cc
+}
+
+fn default_zones() -> u8 {
+    4
+}
+
+/// Reading the state back is left out until it has been seen working on a model
+fn default_capabilities() -> Vec<Capability> {
+    vec![Capability::HardwareEffects]
+}
+
+fn deserialize_hex_id<'de, D: Deserializer<'de>>(deserializer: D) -> std::result::Result<u16, D::Error> {
+    let s = String::deserialize(deserializer)?;
+
+    parse_hex_id(&s).ok_or_else(|| serde::de::Error::custom(format!("\"{s}\" is not a valid hex id")))
+}
+
+impl DeviceDefinition {
+    /// A model missing from the database, assumed to behave like the known ones
+    pub fn unlisted(vendor_id: u16, product_id: u16) -> Self {
+        Self {
+            vendor_id,
+            product_id,
+            usage_page: default_usage_page(),
+            usage: default_usage(),
+            model: "Unlisted keyboard".to_string(),
+            zones: default_zones(),
+            capabilities: default_capabilities(),
+        }
+    }
+
+    pub fn has(&self, capability: Capability) -> bool {
+        self.capabilities.contains(&capability)
+    }
+
+    fn matches(&self, d: &DeviceInfo) -> bool {
+        #[cfg(target_os = "windows")]
+        {
+            (self.vendor_id, self.product_id, self.usage_page, self.usage) == (d.vendor_id(), d.product_id(), d.usage_page(), d.usage())
+        }
+
+        #[cfg(target_os = "linux")]
+        {
+            (self.vendor_id, self.product_id) == (d.vendor_id(), d.product_id())
+        }
+    }
+}
+
+/// The keyboard models the driver will talk to
+///
+/// Starts from the built-in list, which user files can extend or override entry by entry
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DeviceDatabase {
+    devices: Vec<DeviceDefinition>,
+}
+
+impl DeviceDatabase {
+    pub fn builtin() -> Self {
+        Self {
+            devices: serde_json::from_str(BUILTIN_DEVICES).expect("The built-in device database is valid"),
+        }
+    }
+
+    /// Merge a JSON file holding a list of definitions in the same format as the built-in one
+    pub fn merge_file(&mut self, path: &Path) -> Result<()> {
+        let definitions: Vec<DeviceDefinition> = serde_json::from_str(&fs::read_to_string(path)?)?;
+
+        for definition in definitions {
+            self.insert(definition);
+        }
+
+        Ok(())
+    }
+
+    /// Treat a vendor and product id as a supported keyboard, unless the database already knows about it
+    pub fn add_override(&mut self, vendor_id: u16, product_id: u16) {
+        if self.find_by_id(vendor_id, product_id).is_none() {
+            self.devices.push(DeviceDefinition::unlisted(vendor_id, product_id));
+        }
+    }
+
+    /// Add a definition, replacing the one for the same vendor and product id if present
+    pub fn insert(&mut self, definition: DeviceDefinition) {
+        match self.devices.iter_mut().find(|d| (d.vendor_id, d.product_id) == (definition.vendor_id, definition.product_id)) {
+            Some(existing) => *existing = definition,
+            None => self.devices.push(definition),
+        }
+    }
+
+    pub fn devices(&self) -> &[DeviceDefinition] {
+        &self.devices
+    }
+
+    pub fn find_by_id(&self, vendor_id: u16, product_id: u16) -> Option<&DeviceDefinition> {
+        self.devices.iter().find(|d| (d.vendor_id, d.product_id) == (vendor_id, product_id))
+    }
+
+    pub(crate) fn find(&self, d: &DeviceInfo) -> Option<&DeviceDefinition> {
+        self.devices.iter().find(|definition| definition.matches(d))
+    }
+}
+
+/// Replace the built-in database used to look for keyboards
+///
+/// Has to be called before any keyboard is looked up, the database is handed back otherwise
+pub fn set_database(database: DeviceDatabase) -> std::result::Result<(), DeviceDatabase> {
+    DATABASE.set(database)
+}
+
+pub fn database() -> &'static DeviceDatabase {
+    DATABASE.get_or_init(DeviceDatabase::builtin)
+}
+
+pub(crate) fn known_device_for(d: &DeviceInfo) -> Option<&'static DeviceDefinition> {
+    database().find(d)
+}
+
+/// The udev rule giving every user access to a keyboard, as needed to use it without root on Linux
+pub fn udev_rule(vendor_id: u16, product_id: u16) -> String {
+    format!("SUBSYSTEM==\"usb\", ATTR{{idVendor}}==\"{vendor_id:04x}\", ATTR{{idProduct}}==\"{product_id:04x}\", MODE=\"0666\"")
+}
+
+/// A supported keyboard that is currently connected
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct KeyboardInfo {
+    pub path: String,
+    pub serial_number: Option<String>,
+    pub vendor_id: u16,
+    pub product_id: u16,
+    pub model: String,
+    pub zones: u8,
+    pub capabilities: Vec<Capability>,
+}
+
+impl fmt::Display for KeyboardInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({:04x}:{:04x}, {} zones) at {}", self.model, self.vendor_id, self.product_id, self.zones, self.path)?;
+
+        if let Some(serial) = &self.serial_number {
+            write!(f, ", serial {serial}")?;
+        }
+
+        Ok(())
+    }
+}
+
+/// List every connected device matching a known keyboard, in the order hidapi reports them
+pub fn list_keyboards() -> Result<Vec<KeyboardInfo>> {
+    let api: HidApi = HidApi::new()?;
+
+    let mut list: Vec<KeyboardInfo> = Vec::new();
+
+    for d in api.device_list() {
+        let Some(known) = known_device_for(d) else {
+            continue;
+        };
+
+        let path = d.path().to_string_lossy().into_owned();
+
+        // The same device may be listed once per interface
+        if list.iter().any(|info| info.path == path) {
+            continue;
+        }
+
+        list.push(KeyboardInfo {
+            path,
+            serial_number: d.serial_number().filter(|serial| !serial.is_empty()).map(str::to_string),
+            vendor_id: d.vendor_id(),
+            product_id: d.product_id(),
+            model: known.model.clone(),
+            zones: known.zones,
+            capabilities: known.capabilities.clone(),
+        });
+    }
+
+    Ok(list)
+}
+
+/// Picks one keyboard among the connected ones
+///
+/// Parsed from `path:<path>`, `pid:<hex>`, `serial:<serial>` or `<vid>:<pid>` in hex, a bare value is read as a product id if it is valid hex and as a path otherwise
+///
+/// A `<vid>:<pid>` pair can name a keyboard missing from the device database, see [`DeviceDatabase::add_override`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum DeviceSelector {
+    Path(String),
+    ProductId(u16),
+    VendorProductId(u16, u16),
+    Serial(String),
+}
+
+impl DeviceSelector {
+    pub fn matches(&self, info: &KeyboardInfo) -> bool {
+        match self {
+            Self::Path(path) => &info.path == path,
+            Self::ProductId(product_id) => info.product_id == *product_id,
+            Self::VendorProductId(vendor_id, product_id) => (info.vendor_id, info.product_id) == (*vendor_id, *product_id),
+            Self::Serial(serial) => info.serial_number.as_ref() == Some(serial),
+        }
+    }
+}
+
+fn parse_hex_id(s: &str) -> Option<u16> {
+    u16::from_str_radix(s.trim_start_matches("0x"), 16).ok()
+}
+
+impl FromStr for DeviceSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("path:") {
+            Ok(Self::Path(path.to_string()))
+        } else if let Some(product_id) = s.strip_prefix("pid:") {
+            parse_hex_id(product_id).map(Self::ProductId).ok_or_else(|| format!("\"{product_id}\" is not a valid product id"))
+        } else if let Some(serial) = s.strip_prefix("serial:") {
+            Ok(Self::Serial(serial.to_string()))
+        } else if let Some((vendor_id, product_id)) = s.split_once(':').and_then(|(vid, pid)| Some((parse_hex_id(vid)?, parse_hex_id(pid)?))) {
+            Ok(Self::VendorProductId(vendor_id, product_id))
+        } else if let Some(product_id) = parse_hex_id(s) {
+            Ok(Self::ProductId(product_id))
+        } else if s.is_empty() {
+            Err("The device selector cannot be empty".to_string())
+        } else {
+            Ok(Self::Path(s.to_string()))
+        }
+    }
+}
+
+impl fmt::Display for DeviceSelector {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Path(path) => write!(f, "path:{path}"),
+            Self::ProductId(product_id) => write!(f, "pid:{product_id:04x}"),
+            Self::VendorProductId(vendor_id, product_id) => write!(f, "{vendor_id:04x}:{product_id:04x}"),
+            Self::Serial(serial) => write!(f, "serial:{serial}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn selectors_are_parsed() {
+        let cases = [
+            ("path:1-2:1.0", DeviceSelector::Path("1-2:1.0".to_string())),
+            ("pid:c993", DeviceSelector::ProductId(0xc993)),
+            ("pid:0xc993", DeviceSelector::ProductId(0xc993)),
+            ("serial:ABC123", DeviceSelector::Serial("ABC123".to_string())),
+            ("048d:c995", DeviceSelector::VendorProductId(0x048d, 0xc995)),
+            ("c975", DeviceSelector::ProductId(0xc975)),
+            ("/dev/hidraw3", DeviceSelector::Path("/dev/hidraw3".to_string())),
+        ];
+
+        for (input, expected) in cases {
+            assert_eq!(input.parse::<DeviceSelector>().as_ref(), Ok(&expected), "{input}");
+        }
+    }
+
+    #[test]
+    fn invalid_selectors_are_rejected() {
+        assert!("".parse::<DeviceSelector>().is_err());
+        assert!("pid:".parse::<DeviceSelector>().is_err());
+        assert!("pid:zzzz".parse::<DeviceSelector>().is_err());
+        assert!("pid:12345".parse::<DeviceSelector>().is_err());
+    }
+
+    #[test]
+    fn selectors_display_as_they_are_parsed() {
+        for input in ["path:/dev/hidraw3", "pid:c993", "serial:ABC123", "048d:c995"] {
+            assert_eq!(input.parse::<DeviceSelector>().unwrap().to_string(), input);
+        }
+    }
+
+    #[test]
+    fn the_builtin_database_loads() {
+        let database = DeviceDatabase::builtin();
+
+        assert!(database.find_by_id(0x048d, 0xc995).is_some());
+        assert!(database.devices().iter().all(|device| device.zones == 4));
+    }
+
+    #[test]
+    fn overrides_only_add_unknown_models() {
+        let mut database = DeviceDatabase::builtin();
+        let known = database.find_by_id(0x048d, 0xc995).cloned();
+
+        database.add_override(0x048d, 0xc995);
+        database.add_override(0x048d, 0xc999);
+
+        assert_eq!(database.find_by_id(0x048d, 0xc995).cloned(), known);
+        assert_eq!(database.find_by_id(0x048d, 0xc999).map(|device| device.model.as_str()), Some("Unlisted keyboard"));
+    }
+}
@@ -1,28 +1,65 @@
+use std::fmt;
+
 use hidapi::HidError;
 use thiserror::Error;
 
+use crate::transport::PAYLOAD_SIZE;
+
+pub use legion_rgb_protocol::error::{DecodeError, ParseColorError, RangeError, RangeErrorKind};
+
 #[derive(Debug, Error)]
 #[allow(clippy::enum_variant_names)]
 pub enum Error {
-    #[error("HidError: {}", .0)]
-    HidError(#[from] HidError),
+    #[error("HidError: {}{}", .source, .context.as_ref().map_or(String::new(), |context| format!(" ({context})")))]
+    HidError { source: HidError, context: Option<WriteContext> },
     #[error("Error: Couldn't find device")]
     DeviceNotFound,
+    #[error("Error: Not allowed to open the keyboard at {path}. Add the following udev rule (e.g. in /etc/udev/rules.d/99-kblight.rules) and reload the rules:\n{udev_rule}")]
+    PermissionDenied { path: String, udev_rule: String },
+    #[error("Error: The device was disconnected")]
+    Disconnected,
     #[error("Error: {}", .0)]
     RangeError(#[from] RangeError),
+    #[error("IoError: {}", .0)]
+    IoError(#[from] std::io::Error),
+    #[error("Error: The device did not report its current state")]
+    ReadbackUnsupported,
+    #[error("Error: Received a malformed payload")]
+    InvalidPayload,
+    #[error("Error: Line {line} of the capture is not a valid payload")]
+    InvalidCapture { line: usize },
+    #[error("DeviceDatabaseError: {}", .0)]
+    DeviceDatabaseError(#[from] serde_json::Error),
+}
+
+impl From<HidError> for Error {
+    fn from(source: HidError) -> Self {
+        Self::HidError { source, context: None }
+    }
+}
+
+impl From<DecodeError> for Error {
+    fn from(err: DecodeError) -> Self {
+        match err {
+            DecodeError::InvalidPayload => Self::InvalidPayload,
+            DecodeError::RangeError(err) => Self::RangeError(err),
+        }
+    }
 }
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Debug, Error)]
-#[error("RangeError: A value specified was not within the expected range")]
-pub struct RangeError {
-    pub kind: RangeErrorKind,
+/// What was being sent when a write to the keyboard failed
+#[derive(Debug, Clone)]
+pub struct WriteContext {
+    pub payload: [u8; PAYLOAD_SIZE],
+    pub attempts: u32,
 }
 
-#[derive(Debug)]
-pub enum RangeErrorKind {
-    Zone,
-    Speed,
-    Brightness,
+impl fmt::Display for WriteContext {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let payload = self.payload.iter().map(|byte| format!("{byte:02x}")).collect::<Vec<String>>().join(" ");
+
+        write!(f, "sending [{payload}], gave up after {} attempt(s)", self.attempts)
+    }
 }
@@ -1,104 +1,249 @@
+use capture::PayloadRecorder;
+use color::{Rgb, ZoneColors, ZoneId};
+use device::{Capability, DeviceSelector, KeyboardInfo};
 use error::{RangeError, RangeErrorKind, Result};
-use hidapi::{HidApi, HidDevice};
+use hidapi::HidApi;
 use std::{
     sync::{
         atomic::{AtomicBool, Ordering},
         Arc,
     },
     thread,
-    time::Duration,
+    time::{Duration, Instant},
 };
+use transition::Transition;
+use transport::{HidTransport, Transport, PAYLOAD_SIZE};
 
+pub use legion_rgb_protocol::{color, BaseEffects, LightingState, BRIGHTNESS_RANGE, SPEED_RANGE, ZONE_RANGE};
+
+pub mod capture;
+pub mod device;
 pub mod error;
+pub mod transition;
+pub mod transport;
+
+pub const DEFAULT_MAX_FPS: u32 = 60;
 
-const KNOWN_DEVICE_INFOS: [(u16, u16, u16, u16); 11] = [
-    (0x048d, 0xc995, 0xff89, 0x00cc), // 2024 Pro
-    (0x048d, 0xc994, 0xff89, 0x00cc), // 2024
-    (0x048d, 0xc993, 0xff89, 0x00cc), // 2024 LOQ
-    (0x048d, 0xc985, 0xff89, 0x00cc), // 2023 Pro
-    (0x048d, 0xc984, 0xff89, 0x00cc), // 2023
-    (0x048d, 0xc983, 0xff89, 0x00cc), // 2023 LOQ
-    (0x048d, 0xc975, 0xff89, 0x00cc), // 2022
-    (0x048d, 0xc973, 0xff89, 0x00cc), // 2022 Ideapad
-    (0x048d, 0xc965, 0xff89, 0x00cc), // 2021
-    (0x048d, 0xc963, 0xff89, 0x00cc), // 2021 Ideapad
-    (0x048d, 0xc955, 0xff89, 0x00cc), // 2020
-];
-
-pub const SPEED_RANGE: std::ops::RangeInclusive<u8> = 1..=4;
-pub const BRIGHTNESS_RANGE: std::ops::RangeInclusive<u8> = 1..=2;
-pub const ZONE_RANGE: std::ops::RangeInclusive<u8> = 0..=3;
-
-pub enum BaseEffects {
-    Static,
-    Breath,
-    Smooth,
-    LeftWave,
-    RightWave,
+/// How many times a failed write is attempted before giving up, the wait between attempts doubling each time
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub backoff: Duration,
 }
 
-pub struct LightingState {
-    effect_type: BaseEffects,
-    speed: u8,
-    brightness: u8,
-    rgb_values: [u8; 12],
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            attempts: 1,
+            backoff: Duration::from_millis(10),
+        }
+    }
 }
 
 pub struct Keyboard {
-    keyboard_hid: HidDevice,
+    transport: Box<dyn Transport>,
     current_state: LightingState,
+    /// What the keyboard was displaying before anything was sent to it, when it could be read
+    startup_state: Option<LightingState>,
     stop_signal: Arc<AtomicBool>,
+    retry_policy: RetryPolicy,
+    recorder: Option<PayloadRecorder>,
+    last_payload: Option<[u8; PAYLOAD_SIZE]>,
+    last_write: Option<Instant>,
+    min_write_interval: Option<Duration>,
+    software_brightness: u8,
 }
 
 #[allow(dead_code)]
 impl Keyboard {
-    fn build_payload(&self) -> Result<[u8; 33]> {
-        let keyboard_state = &self.current_state;
+    /// Build a keyboard on top of an arbitrary transport
+    ///
+    /// The current state is read back from the device when possible so that whatever was already being displayed is kept,
+    /// otherwise an all-black static state is pushed to it
+    pub fn with_transport(transport: impl Transport + 'static, stop_signal: Arc<AtomicBool>) -> Result<Self> {
+        Self::new(transport, stop_signal, true)
+    }
 
-        if !SPEED_RANGE.contains(&keyboard_state.speed) {
-            return Err(RangeError { kind: RangeErrorKind::Speed }.into());
+    fn new(transport: impl Transport + 'static, stop_signal: Arc<AtomicBool>, read_back: bool) -> Result<Self> {
+        let mut keyboard = Self {
+            transport: Box::new(transport),
+            current_state: LightingState::default(),
+            startup_state: None,
+            stop_signal,
+            retry_policy: RetryPolicy::default(),
+            recorder: None,
+            last_payload: None,
+            last_write: None,
+            min_write_interval: None,
+            software_brightness: 100,
+        };
+
+        keyboard.set_max_fps(Some(DEFAULT_MAX_FPS));
+
+        // Read before the first write, which would otherwise replace whatever was being displayed
+        keyboard.startup_state = if read_back { keyboard.read_state().ok() } else { None };
+
+        if keyboard.startup_state.is_none() {
+            keyboard.refresh()?;
         }
-        if !BRIGHTNESS_RANGE.contains(&keyboard_state.brightness) {
-            return Err(RangeError { kind: RangeErrorKind::Brightness }.into());
+
+        Ok(keyboard)
+    }
+
+    /// Failed writes are not retried by default
+    pub fn set_retry_policy(&mut self, retry_policy: RetryPolicy) {
+        self.retry_policy = retry_policy;
+    }
+
+    /// Cap how many payloads are sent per second, pass `None` to send them as fast as they come
+    ///
+    /// Frames of a transition that come too early are dropped, any other write waits for its turn
+    pub fn set_max_fps(&mut self, max_fps: Option<u32>) {
+        self.min_write_interval = max_fps.filter(|fps| *fps > 0).map(|fps| Duration::from_secs(1) / fps);
+    }
+
+    /// Dim the colors to `percent` (capped at 100) of their value on top of the hardware brightness
+    ///
+    /// Only the colors that are sent are scaled, [`Self::current_state`] keeps the requested ones. Effects run by the keyboard itself
+    /// (smooth and wave) pick their own colors and are not affected.
+    pub fn set_software_brightness(&mut self, percent: u8) -> Result<()> {
+        self.software_brightness = percent.min(100);
+        self.refresh()
+    }
+
+    pub fn software_brightness(&self) -> u8 {
+        self.software_brightness
+    }
+
+    /// Log every payload sent from now on, pass `None` to stop
+    pub fn set_recorder(&mut self, recorder: Option<PayloadRecorder>) {
+        self.recorder = recorder;
+    }
+
+    /// The state that was last sent to (or read from) the keyboard
+    pub fn current_state(&self) -> &LightingState {
+        &self.current_state
+    }
+
+    /// What the keyboard was displaying when it was opened, only known for the models that can be read from
+    ///
+    /// Nothing had been sent to the keyboard yet when it was read, so it is what the firmware or another program left it on
+    pub fn startup_state(&self) -> Option<&LightingState> {
+        self.startup_state.as_ref()
+    }
+
+    /// Query the controller for what it is currently displaying and adopt it as the current state
+    pub fn read_state(&mut self) -> Result<LightingState> {
+        let mut buffer = [0; PAYLOAD_SIZE];
+        let read = self.transport.get_feature_report(&mut buffer)?;
+
+        if read < PAYLOAD_SIZE {
+            return Err(error::Error::InvalidPayload);
         }
 
-        let mut payload: [u8; 33] = [0; 33];
-        payload[0] = 0xcc;
-        payload[1] = 0x16;
-        payload[2] = match keyboard_state.effect_type {
-            BaseEffects::Static => 0x01,
-            BaseEffects::Breath => 0x03,
-            BaseEffects::Smooth => 0x06,
-            BaseEffects::LeftWave => {
-                payload[19] = 0x1;
-                0x04
-            }
-            BaseEffects::RightWave => {
-                payload[18] = 0x1;
-                0x04
-            }
-        };
+        self.current_state = LightingState::from_payload(&buffer)?;
 
-        payload[3] = keyboard_state.speed;
-        payload[4] = keyboard_state.brightness;
+        Ok(self.current_state.clone())
+    }
 
-        if let BaseEffects::Static | BaseEffects::Breath = keyboard_state.effect_type {
-            payload[5..(12 + 5)].copy_from_slice(&keyboard_state.rgb_values[..12]);
-        };
+    fn build_payload(&self) -> Result<[u8; PAYLOAD_SIZE]> {
+        let mut state = self.current_state.clone();
+        state.set_colors(state.colors().dimmed(self.software_brightness));
 
-        Ok(payload)
+        Ok(state.to_payload()?)
     }
 
     pub fn refresh(&mut self) -> Result<()> {
+        self.push_state(false)
+    }
+
+    /// Send the current state unless the keyboard is already displaying it
+    ///
+    /// Droppable frames are skipped instead of waited on when going over the maximum FPS
+    fn push_state(&mut self, droppable: bool) -> Result<()> {
         let payload = self.build_payload()?;
 
-        self.keyboard_hid.send_feature_report(&payload).unwrap();
+        if self.last_payload == Some(payload) {
+            return Ok(());
+        }
 
-        Ok(())
+        if let Some(wait) = self.time_until_next_write() {
+            if droppable {
+                return Ok(());
+            }
+
+            thread::sleep(wait);
+        }
+
+        self.write_payload(&payload)
+    }
+
+    fn time_until_next_write(&self) -> Option<Duration> {
+        let next_write = self.last_write? + self.min_write_interval?;
+
+        next_write.checked_duration_since(Instant::now())
+    }
+
+    /// Send a payload as-is (e.g. one from a capture), adopting the state it describes
+    ///
+    /// Unlike other writes, it is neither skipped when repeated nor held back by the maximum FPS
+    pub fn send_payload(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        self.current_state = LightingState::from_payload(payload)?;
+
+        self.write_payload(payload)
+    }
+
+    fn write_payload(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(payload)?;
+        }
+
+        let mut backoff = self.retry_policy.backoff;
+        let mut attempts = 1;
+
+        loop {
+            match self.transport.send_feature_report(payload) {
+                Ok(()) => {
+                    self.last_payload = Some(*payload);
+                    self.last_write = Some(Instant::now());
+
+                    return Ok(());
+                }
+                Err(error::Error::HidError { source, .. }) => {
+                    if attempts >= self.retry_policy.attempts {
+                        // A failed write is most commonly the device going away (suspend, USB resets...), only look for it once giving up as that is slow
+                        if !self.transport.is_connected() {
+                            return Err(error::Error::Disconnected);
+                        }
+
+                        let context = error::WriteContext { payload: *payload, attempts };
+                        return Err(error::Error::HidError { source, context: Some(context) });
+                    }
+
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempts += 1;
+                }
+                // There is no point in retrying if the error didn't come from hidapi
+                Err(err) => return Err(err),
+            }
+        }
+    }
+
+    /// Stage several changes to the state and send them all at once with [`Transaction::commit`]
+    pub fn transaction(&mut self) -> Transaction<'_> {
+        let staged = self.current_state.clone();
+        let software_brightness = self.software_brightness;
+
+        Transaction {
+            keyboard: self,
+            staged,
+            software_brightness,
+        }
     }
 
     pub fn set_effect(&mut self, effect: BaseEffects) -> Result<()> {
-        self.current_state.effect_type = effect;
+        self.current_state.set_effect_type(effect);
         self.refresh()?;
 
         Ok(())
@@ -109,7 +254,7 @@ impl Keyboard {
             return Err(RangeError { kind: RangeErrorKind::Speed }.into());
         }
 
-        self.current_state.speed = speed;
+        self.current_state.set_speed(speed);
         self.refresh()?;
 
         Ok(())
@@ -120,56 +265,39 @@ impl Keyboard {
             return Err(RangeError { kind: RangeErrorKind::Brightness }.into());
         }
         let brightness = brightness.clamp(BRIGHTNESS_RANGE.min().unwrap(), BRIGHTNESS_RANGE.max().unwrap());
-        self.current_state.brightness = brightness;
+        self.current_state.set_brightness(brightness);
         self.refresh()?;
 
         Ok(())
     }
 
-    pub fn set_zone_by_index(&mut self, zone_index: u8, new_values: [u8; 3]) -> Result<()> {
-        if !ZONE_RANGE.contains(&zone_index) {
-            return Err(RangeError { kind: RangeErrorKind::Zone }.into());
-        }
-
-        for (i, _) in new_values.iter().enumerate() {
-            let full_index = (zone_index * 3 + i as u8) as usize;
-            self.current_state.rgb_values[full_index] = new_values[i];
-        }
+    pub fn set_zone(&mut self, zone: ZoneId, color: Rgb) -> Result<()> {
+        self.current_state.colors_mut()[zone] = color;
         self.refresh()?;
 
         Ok(())
     }
 
-    pub fn set_colors_to(&mut self, new_values: &[u8; 12]) -> Result<()> {
-        if let BaseEffects::Static | BaseEffects::Breath = self.current_state.effect_type {
-            for (i, _) in new_values.iter().enumerate() {
-                self.current_state.rgb_values[i] = new_values[i];
-            }
+    pub fn set_colors(&mut self, colors: &ZoneColors) -> Result<()> {
+        if let BaseEffects::Static | BaseEffects::Breath = self.current_state.effect_type() {
+            self.current_state.set_colors(*colors);
             self.refresh()?;
         }
 
         Ok(())
     }
 
-    pub fn solid_set_colors_to(&mut self, new_values: [u8; 3]) -> Result<()> {
-        if let BaseEffects::Static | BaseEffects::Breath = self.current_state.effect_type {
-            for i in (0..12).step_by(3) {
-                self.current_state.rgb_values[i] = new_values[0];
-                self.current_state.rgb_values[i + 1] = new_values[1];
-                self.current_state.rgb_values[i + 2] = new_values[2];
-            }
-            self.refresh()?;
-        }
-
-        Ok(())
+    pub fn set_solid_color(&mut self, color: Rgb) -> Result<()> {
+        self.set_colors(&ZoneColors::solid(color))
     }
 
-    pub fn transition_colors_to(&mut self, target_colors: &[u8; 12], steps: u8, delay_between_steps: u64) -> Result<()> {
-        if let BaseEffects::Static | BaseEffects::Breath = self.current_state.effect_type {
-            let mut new_values = self.current_state.rgb_values.map(f32::from);
+    pub fn transition_colors(&mut self, target_colors: &ZoneColors, steps: u8, delay_between_steps: u64) -> Result<()> {
+        if let BaseEffects::Static | BaseEffects::Breath = self.current_state.effect_type() {
+            let target_values = target_colors.to_array();
+            let mut new_values = self.current_state.rgb_values().map(f32::from);
             let mut color_differences: [f32; 12] = [0.0; 12];
             for index in 0..12 {
-                color_differences[index] = (f32::from(target_colors[index]) - f32::from(self.current_state.rgb_values[index])) / f32::from(steps);
+                color_differences[index] = (f32::from(target_values[index]) - new_values[index]) / f32::from(steps);
             }
             if !self.stop_signal.load(Ordering::SeqCst) {
                 for _step_num in 1..=steps {
@@ -179,57 +307,148 @@ impl Keyboard {
                     for (index, _) in color_differences.iter().enumerate() {
                         new_values[index] += color_differences[index];
                     }
-                    self.current_state.rgb_values = new_values.map(|val| val as u8);
+                    self.current_state.set_colors(ZoneColors::from_array(new_values.map(|val| val.round() as u8)));
 
-                    self.refresh()?;
+                    self.push_state(true)?;
                     thread::sleep(Duration::from_millis(delay_between_steps));
                 }
-                self.set_colors_to(target_colors)?;
+                self.set_colors(target_colors)?;
             }
         }
 
         Ok(())
     }
+
+    /// Blend from the current colors to the target ones over the duration of the transition
+    ///
+    /// Frames are sent as fast as the maximum FPS allows, the target colors are always set at the end unless the keyboard was stopped beforehand
+    pub fn transition_to(&mut self, target_colors: &ZoneColors, transition: &Transition) -> Result<()> {
+        if let BaseEffects::Static | BaseEffects::Breath = self.current_state.effect_type() {
+            if self.stop_signal.load(Ordering::SeqCst) {
+                return Ok(());
+            }
+
+            let start_colors = self.current_state.colors();
+            let frame_interval = self.min_write_interval.unwrap_or(Duration::from_secs(1) / DEFAULT_MAX_FPS);
+            let start = Instant::now();
+
+            while start.elapsed() < transition.duration && !self.stop_signal.load(Ordering::SeqCst) {
+                self.current_state.set_colors(transition.colors_at(&start_colors, target_colors, start.elapsed()));
+
+                self.push_state(true)?;
+                thread::sleep(frame_interval);
+            }
+
+            self.set_colors(target_colors)?;
+        }
+
+        Ok(())
+    }
+
+    // Raw array counterparts of the methods above
+
+    pub fn set_zone_by_index(&mut self, zone_index: u8, new_values: [u8; 3]) -> Result<()> {
+        let zone = ZoneId::try_from(zone_index)?;
+
+        self.set_zone(zone, Rgb::from(new_values))
+    }
+
+    pub fn set_colors_to(&mut self, new_values: &[u8; 12]) -> Result<()> {
+        self.set_colors(&ZoneColors::from_array(*new_values))
+    }
+
+    pub fn solid_set_colors_to(&mut self, new_values: [u8; 3]) -> Result<()> {
+        self.set_solid_color(Rgb::from(new_values))
+    }
+
+    pub fn transition_colors_to(&mut self, target_colors: &[u8; 12], steps: u8, delay_between_steps: u64) -> Result<()> {
+        self.transition_colors(&ZoneColors::from_array(*target_colors), steps, delay_between_steps)
+    }
 }
 
+/// Changes to a keyboard's state that are only sent once committed, dropping it discards them
+#[must_use = "nothing is sent to the keyboard until the transaction is committed"]
+pub struct Transaction<'a> {
+    keyboard: &'a mut Keyboard,
+    staged: LightingState,
+    software_brightness: u8,
+}
+
+impl Transaction<'_> {
+    pub fn effect(mut self, effect: BaseEffects) -> Self {
+        self.staged.set_effect_type(effect);
+        self
+    }
+
+    pub fn speed(mut self, speed: u8) -> Self {
+        self.staged.set_speed(speed);
+        self
+    }
+
+    pub fn brightness(mut self, brightness: u8) -> Self {
+        self.staged.set_brightness(brightness);
+        self
+    }
+
+    pub fn zone(mut self, zone: ZoneId, color: Rgb) -> Self {
+        self.staged.colors_mut()[zone] = color;
+        self
+    }
+
+    pub fn colors(mut self, colors: &ZoneColors) -> Self {
+        self.staged.set_colors(*colors);
+        self
+    }
+
+    /// See [`Keyboard::set_software_brightness`]
+    pub fn software_brightness(mut self, percent: u8) -> Self {
+        self.software_brightness = percent.min(100);
+        self
+    }
+
+    /// Send the staged state in a single write, the keyboard is left untouched if it is out of range
+    pub fn commit(self) -> Result<()> {
+        self.staged.to_payload()?;
+
+        self.keyboard.current_state = self.staged;
+        self.keyboard.software_brightness = self.software_brightness;
+        self.keyboard.refresh()
+    }
+}
+
+/// Open the first supported keyboard found
 pub fn get_keyboard(stop_signal: Arc<AtomicBool>) -> Result<Keyboard> {
     let api: HidApi = HidApi::new()?;
 
-    let info = api
+    let (info, definition) = api
         .device_list()
-        .find(|d| {
-            #[cfg(target_os = "windows")]
-            {
-                let info_tuple = (d.vendor_id(), d.product_id(), d.usage_page(), d.usage());
+        .find_map(|d| device::known_device_for(d).map(|definition| (d, definition)))
+        .ok_or(error::Error::DeviceNotFound)?;
 
-                KNOWN_DEVICE_INFOS.iter().any(|known| known == &info_tuple)
-            }
+    let transport = HidTransport::open(&api, info)?;
 
-            #[cfg(target_os = "linux")]
-            {
-                let info_tuple = (d.vendor_id(), d.product_id());
+    Keyboard::new(transport, stop_signal, definition.has(Capability::Readback))
+}
 
-                KNOWN_DEVICE_INFOS.iter().any(|known| (known.0, known.1) == info_tuple)
-            }
-        })
+/// Open a specific keyboard as returned by [`device::list_keyboards`]
+pub fn open_keyboard(keyboard_info: &KeyboardInfo, stop_signal: Arc<AtomicBool>) -> Result<Keyboard> {
+    let api: HidApi = HidApi::new()?;
+
+    let info = api
+        .device_list()
+        .find(|d| d.path().to_string_lossy() == keyboard_info.path.as_str())
         .ok_or(error::Error::DeviceNotFound)?;
 
-    let keyboard_hid: HidDevice = info.open_device(&api)?;
-    let current_state: LightingState = LightingState {
-        effect_type: BaseEffects::Static,
-        speed: 1,
-        brightness: 1,
-        rgb_values: [0; 12],
-    };
-
-    let mut keyboard = Keyboard {
-        keyboard_hid,
-        current_state,
-        stop_signal,
-    };
-
-    keyboard.refresh()?;
-    Ok(keyboard)
+    let transport = HidTransport::open(&api, info)?;
+
+    Keyboard::new(transport, stop_signal, keyboard_info.capabilities.contains(&Capability::Readback))
+}
+
+/// Open the first connected keyboard matching the selector
+pub fn select_keyboard(selector: &DeviceSelector, stop_signal: Arc<AtomicBool>) -> Result<Keyboard> {
+    let keyboard_info = device::list_keyboards()?.into_iter().find(|info| selector.matches(info)).ok_or(error::Error::DeviceNotFound)?;
+
+    open_keyboard(&keyboard_info, stop_signal)
 }
 
 pub fn find_possible_keyboards() -> Result<Vec<String>> {
@@ -0,0 +1,275 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::color::{Rgb, ZoneColors};
+
+/// How the progress of a transition is spread over its duration
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum Easing {
+    #[default]
+    Linear,
+    /// Starts slow and speeds up
+    EaseIn,
+    /// Starts fast and slows down
+    EaseOut,
+    /// Slow at both ends
+    EaseInOut,
+    /// Like [`Easing::EaseInOut`] but with a steeper middle
+    Cubic,
+    /// Follows half a sine wave, the gentlest of the curves
+    Sine,
+}
+
+impl Easing {
+    /// Map the linear progress `t` (between 0 and 1) to the eased one
+    pub fn apply(self, t: f32) -> f32 {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Self::Linear => t,
+            Self::EaseIn => t * t,
+            Self::EaseOut => 1.0 - (1.0 - t) * (1.0 - t),
+            Self::EaseInOut => {
+                if t < 0.5 {
+                    2.0 * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(2) / 2.0
+                }
+            }
+            Self::Cubic => {
+                if t < 0.5 {
+                    4.0 * t * t * t
+                } else {
+                    1.0 - (-2.0 * t + 2.0).powi(3) / 2.0
+                }
+            }
+            Self::Sine => (1.0 - (std::f32::consts::PI * t).cos()) / 2.0,
+        }
+    }
+}
+
+/// The color space colors are blended in
+///
+/// Blending in sRGB is the cheapest but goes through muddy colors (red to green passes through brown),
+/// OKLab keeps the perceived lightness even and HSV goes around the color wheel
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Deserialize, Serialize)]
+pub enum ColorSpace {
+    #[default]
+    Srgb,
+    LinearRgb,
+    Hsv,
+    Oklab,
+}
+
+impl ColorSpace {
+    /// The color `t` (between 0 and 1) of the way from `from` to `to`
+    pub fn interpolate(self, from: Rgb, to: Rgb, t: f32) -> Rgb {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            Self::Srgb => from_unit(lerp3(to_unit(from), to_unit(to), t)),
+            Self::LinearRgb => {
+                let mixed = lerp3(to_unit(from).map(srgb_to_linear), to_unit(to).map(srgb_to_linear), t);
+                from_unit(mixed.map(linear_to_srgb))
+            }
+            Self::Hsv => {
+                let (from_hue, from_saturation, from_value) = from.to_hsv();
+                let (to_hue, to_saturation, to_value) = to.to_hsv();
+
+                // Grays and black have no meaningful hue, use the other end's so the hue doesn't sweep around
+                let from_hue = if from_saturation == 0.0 { to_hue } else { from_hue };
+                let to_hue = if to_saturation == 0.0 { from_hue } else { to_hue };
+
+                // Go around the shortest way
+                let hue_difference = (to_hue - from_hue + 540.0).rem_euclid(360.0) - 180.0;
+
+                Rgb::from_hsv(from_hue + hue_difference * t, lerp(from_saturation, to_saturation, t), lerp(from_value, to_value, t))
+            }
+            Self::Oklab => {
+                let mixed = lerp3(rgb_to_oklab(from), rgb_to_oklab(to), t);
+                oklab_to_rgb(mixed)
+            }
+        }
+    }
+
+    pub fn interpolate_zones(self, from: &ZoneColors, to: &ZoneColors, t: f32) -> ZoneColors {
+        ZoneColors(std::array::from_fn(|i| self.interpolate(from.0[i], to.0[i], t)))
+    }
+}
+
+/// A timed transition between two sets of colors
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Transition {
+    pub duration: Duration,
+    pub easing: Easing,
+    pub color_space: ColorSpace,
+}
+
+impl Transition {
+    pub fn new(duration: Duration, easing: Easing, color_space: ColorSpace) -> Self {
+        Self { duration, easing, color_space }
+    }
+
+    /// The colors at `elapsed` into the transition
+    pub fn colors_at(&self, from: &ZoneColors, to: &ZoneColors, elapsed: Duration) -> ZoneColors {
+        let progress = if self.duration.is_zero() { 1.0 } else { elapsed.as_secs_f32() / self.duration.as_secs_f32() };
+
+        self.color_space.interpolate_zones(from, to, self.easing.apply(progress))
+    }
+}
+
+impl Default for Transition {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500), Easing::default(), ColorSpace::default())
+    }
+}
+
+fn lerp(from: f32, to: f32, t: f32) -> f32 {
+    from + (to - from) * t
+}
+
+fn lerp3(from: [f32; 3], to: [f32; 3], t: f32) -> [f32; 3] {
+    [lerp(from[0], to[0], t), lerp(from[1], to[1], t), lerp(from[2], to[2], t)]
+}
+
+fn to_unit(color: Rgb) -> [f32; 3] {
+    <[u8; 3]>::from(color).map(|c| f32::from(c) / 255.0)
+}
+
+fn from_unit(channels: [f32; 3]) -> Rgb {
+    Rgb::from(channels.map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+}
+
+fn srgb_to_linear(c: f32) -> f32 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(c: f32) -> f32 {
+    if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+// See https://bottosson.github.io/posts/oklab/
+
+fn rgb_to_oklab(color: Rgb) -> [f32; 3] {
+    let [r, g, b] = to_unit(color).map(srgb_to_linear);
+
+    let l = (0.412_221_46 * r + 0.536_332_55 * g + 0.051_445_995 * b).cbrt();
+    let m = (0.211_903_5 * r + 0.680_699_5 * g + 0.107_396_96 * b).cbrt();
+    let s = (0.088_302_46 * r + 0.281_718_85 * g + 0.629_978_7 * b).cbrt();
+
+    [
+        0.210_454_26 * l + 0.793_617_8 * m - 0.004_072_047 * s,
+        1.977_998_5 * l - 2.428_592_2 * m + 0.450_593_7 * s,
+        0.025_904_037 * l + 0.782_771_77 * m - 0.808_675_77 * s,
+    ]
+}
+
+fn oklab_to_rgb([lightness, a, b]: [f32; 3]) -> Rgb {
+    let l = (lightness + 0.396_337_78 * a + 0.215_803_76 * b).powi(3);
+    let m = (lightness - 0.105_561_346 * a - 0.063_854_17 * b).powi(3);
+    let s = (lightness - 0.089_484_18 * a - 1.291_485_5 * b).powi(3);
+
+    let linear = [
+        4.076_741_7 * l - 3.307_711_6 * m + 0.230_969_94 * s,
+        -1.268_438 * l + 2.609_757_4 * m - 0.341_319_38 * s,
+        -0.004_196_086_3 * l - 0.703_418_6 * m + 1.707_614_7 * s,
+    ];
+
+    from_unit(linear.map(|c| linear_to_srgb(c.clamp(0.0, 1.0))))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const COLORS: [Rgb; 8] = [
+        Rgb::BLACK,
+        Rgb::WHITE,
+        Rgb::new(255, 0, 0),
+        Rgb::new(0, 255, 0),
+        Rgb::new(0, 0, 255),
+        Rgb::new(128, 128, 128),
+        Rgb::new(255, 128, 0),
+        Rgb::new(12, 34, 56),
+    ];
+
+    const COLOR_SPACES: [ColorSpace; 4] = [ColorSpace::Srgb, ColorSpace::LinearRgb, ColorSpace::Hsv, ColorSpace::Oklab];
+
+    const EASINGS: [Easing; 6] = [Easing::Linear, Easing::EaseIn, Easing::EaseOut, Easing::EaseInOut, Easing::Cubic, Easing::Sine];
+
+    #[test]
+    fn interpolation_starts_and_ends_on_the_given_colors() {
+        for color_space in COLOR_SPACES {
+            for from in COLORS {
+                for to in COLORS {
+                    assert_eq!(color_space.interpolate(from, to, 0.0), from, "{color_space:?} from {from} to {to}");
+                    assert_eq!(color_space.interpolate(from, to, 1.0), to, "{color_space:?} from {from} to {to}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn interpolation_clamps_the_progress() {
+        let (from, to) = (Rgb::new(255, 0, 0), Rgb::new(0, 0, 255));
+
+        for color_space in COLOR_SPACES {
+            assert_eq!(color_space.interpolate(from, to, -1.0), from, "{color_space:?}");
+            assert_eq!(color_space.interpolate(from, to, 2.0), to, "{color_space:?}");
+        }
+    }
+
+    #[test]
+    fn hsv_goes_around_the_shortest_way() {
+        // Red to blue is shorter through magenta than through green
+        assert_eq!(ColorSpace::Hsv.interpolate(Rgb::new(255, 0, 0), Rgb::new(0, 0, 255), 0.5), Rgb::new(255, 0, 255));
+    }
+
+    #[test]
+    fn easings_start_at_zero_and_end_at_one() {
+        for easing in EASINGS {
+            assert!(easing.apply(0.0).abs() < 1e-6, "{easing:?}");
+            assert!((easing.apply(1.0) - 1.0).abs() < 1e-6, "{easing:?}");
+        }
+    }
+
+    #[test]
+    fn easings_never_go_backwards() {
+        for easing in EASINGS {
+            let progress: Vec<f32> = (0..=100).map(|i| easing.apply(i as f32 / 100.0)).collect();
+
+            assert!(progress.windows(2).all(|pair| pair[0] <= pair[1]), "{easing:?}");
+        }
+    }
+
+    #[test]
+    fn transitions_reach_the_target_colors() {
+        let from = ZoneColors::solid(Rgb::new(255, 0, 0));
+        let to = ZoneColors([Rgb::new(0, 255, 0), Rgb::new(0, 0, 255), Rgb::WHITE, Rgb::BLACK]);
+
+        for color_space in COLOR_SPACES {
+            let transition = Transition::new(Duration::from_millis(400), Easing::EaseInOut, color_space);
+
+            assert_eq!(transition.colors_at(&from, &to, Duration::ZERO), from, "{color_space:?}");
+            assert_eq!(transition.colors_at(&from, &to, Duration::from_millis(400)), to, "{color_space:?}");
+            assert_eq!(transition.colors_at(&from, &to, Duration::from_secs(10)), to, "{color_space:?}");
+        }
+    }
+
+    #[test]
+    fn instant_transitions_jump_to_the_target_colors() {
+        let transition = Transition::new(Duration::ZERO, Easing::Linear, ColorSpace::Oklab);
+        let to = ZoneColors::solid(Rgb::new(0, 128, 255));
+
+        assert_eq!(transition.colors_at(&ZoneColors::default(), &to, Duration::ZERO), to);
+    }
+}
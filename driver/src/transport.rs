@@ -0,0 +1,165 @@
+use std::{
+    ffi::{CStr, CString},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+use hidapi::{DeviceInfo, HidApi, HidDevice, HidError};
+
+use crate::{
+    capture::PayloadRecorder,
+    device,
+    error::{Error, Result},
+};
+
+pub use legion_rgb_protocol::PAYLOAD_SIZE;
+
+/// Anything the keyboard can push its feature reports to
+pub trait Transport: Send {
+    fn send_feature_report(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()>;
+
+    /// Ask the other end for the report it currently holds, not every transport can answer
+    fn get_feature_report(&mut self, _buffer: &mut [u8; PAYLOAD_SIZE]) -> Result<usize> {
+        Err(Error::ReadbackUnsupported)
+    }
+
+    /// Whether the other end is still there, asked once writes keep failing to tell a disconnection apart from other errors
+    fn is_connected(&self) -> bool {
+        true
+    }
+}
+
+/// The real thing, backed by a device opened through hidapi
+pub struct HidTransport {
+    device: HidDevice,
+    path: CString,
+}
+
+impl HidTransport {
+    pub fn open(api: &HidApi, info: &DeviceInfo) -> Result<Self> {
+        let device = info.open_device(api).map_err(|err| {
+            if lacks_permission(info, &err) {
+                Error::PermissionDenied {
+                    path: info.path().to_string_lossy().into_owned(),
+                    udev_rule: device::udev_rule(info.vendor_id(), info.product_id()),
+                }
+            } else {
+                err.into()
+            }
+        })?;
+
+        Ok(Self { device, path: info.path().to_owned() })
+    }
+}
+
+/// Whether a device failed to open because the user isn't allowed to access it, which on Linux means a missing udev rule
+fn lacks_permission(info: &DeviceInfo, err: &HidError) -> bool {
+    if let Some(node) = device_node(info.path()) {
+        return matches!(std::fs::OpenOptions::new().read(true).write(true).open(node), Err(err) if err.kind() == std::io::ErrorKind::PermissionDenied);
+    }
+
+    // Fall back to what hidapi reported, libusb calls it LIBUSB_ERROR_ACCESS
+    let message = err.to_string().to_lowercase();
+    message.contains("access") || message.contains("permission denied")
+}
+
+/// The libusb backend names devices `<bus>:<address>:<interface>` in hex, which maps to a node under /dev/bus/usb
+#[cfg(target_os = "linux")]
+fn device_node(path: &CStr) -> Option<PathBuf> {
+    let path = path.to_str().ok()?;
+    let mut parts = path.split(':');
+
+    let bus = u16::from_str_radix(parts.next()?, 16).ok()?;
+    let address = u16::from_str_radix(parts.next()?, 16).ok()?;
+
+    Some(format!("/dev/bus/usb/{bus:03}/{address:03}").into())
+}
+
+/// Other platforms name devices after something that isn't a file
+#[cfg(not(target_os = "linux"))]
+fn device_node(_path: &CStr) -> Option<PathBuf> {
+    None
+}
+
+impl Transport for HidTransport {
+    fn send_feature_report(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        Ok(self.device.send_feature_report(payload)?)
+    }
+
+    /// Whether the node of the device this transport was opened on is still there, a new one being made when it is plugged back in
+    ///
+    /// Platforms without such a node go through every device hidapi can find instead, which is a lot slower
+    fn is_connected(&self) -> bool {
+        match device_node(&self.path) {
+            Some(node) => node.exists(),
+            None => HidApi::new().is_ok_and(|api| api.device_list().any(|d| d.path() == self.path.as_c_str())),
+        }
+    }
+
+    fn get_feature_report(&mut self, buffer: &mut [u8; PAYLOAD_SIZE]) -> Result<usize> {
+        // The first byte selects the report id
+        buffer[0] = 0xcc;
+
+        Ok(self.device.get_feature_report(buffer)?)
+    }
+}
+
+/// Keeps every report in memory, clones share the same recording so one can be handed to a [`crate::Keyboard`] while another is inspected
+#[derive(Clone, Default)]
+pub struct MockTransport {
+    reports: Arc<Mutex<Vec<[u8; PAYLOAD_SIZE]>>>,
+}
+
+impl MockTransport {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// All the reports sent so far, oldest first
+    pub fn reports(&self) -> Vec<[u8; PAYLOAD_SIZE]> {
+        self.reports.lock().unwrap().clone()
+    }
+
+    pub fn last_report(&self) -> Option<[u8; PAYLOAD_SIZE]> {
+        self.reports.lock().unwrap().last().copied()
+    }
+
+    pub fn clear(&self) {
+        self.reports.lock().unwrap().clear();
+    }
+}
+
+impl Transport for MockTransport {
+    fn send_feature_report(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        self.reports.lock().unwrap().push(*payload);
+
+        Ok(())
+    }
+
+    /// Answers with the last report it was sent, like the real controller would
+    fn get_feature_report(&mut self, buffer: &mut [u8; PAYLOAD_SIZE]) -> Result<usize> {
+        let last = self.last_report().ok_or(Error::ReadbackUnsupported)?;
+        *buffer = last;
+
+        Ok(PAYLOAD_SIZE)
+    }
+}
+
+/// Writes every report to a file in the capture format, see [`PayloadRecorder`]
+pub struct FileTransport {
+    recorder: PayloadRecorder,
+}
+
+impl FileTransport {
+    pub fn create(path: &Path) -> Result<Self> {
+        Ok(Self {
+            recorder: PayloadRecorder::create(path)?,
+        })
+    }
+}
+
+impl Transport for FileTransport {
+    fn send_feature_report(&mut self, payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        self.recorder.record(payload)
+    }
+}
@@ -0,0 +1,152 @@
+use std::sync::{atomic::AtomicBool, Arc};
+
+use legion_rgb_driver::{
+    color::{Rgb, ZoneColors},
+    error::{Error, Result},
+    transport::{MockTransport, Transport, PAYLOAD_SIZE},
+    BaseEffects, Keyboard, LightingState,
+};
+
+/// A keyboard writing to a fresh mock, without any FPS cap so that the tests don't wait on it
+fn keyboard() -> (Keyboard, MockTransport) {
+    let mock = MockTransport::new();
+    let mut keyboard = Keyboard::with_transport(mock.clone(), Arc::new(AtomicBool::new(false))).unwrap();
+    keyboard.set_max_fps(None);
+
+    (keyboard, mock)
+}
+
+const COLORS: ZoneColors = ZoneColors([Rgb::new(255, 0, 0), Rgb::new(0, 255, 0), Rgb::new(0, 0, 255), Rgb::new(10, 20, 30)]);
+
+#[test]
+fn opening_without_readback_sends_a_black_static_state() {
+    let (keyboard, mock) = keyboard();
+
+    assert_eq!(mock.reports().len(), 1);
+    assert_eq!(keyboard.current_state(), &LightingState::default());
+    assert_eq!(keyboard.startup_state(), None);
+    assert_eq!(mock.last_report().unwrap()[..5], [0xcc, 0x16, 0x01, 0x01, 0x01]);
+}
+
+#[test]
+fn opening_adopts_the_state_read_back() {
+    let (mut writer, mock) = keyboard();
+    writer.transaction().effect(BaseEffects::Breath).speed(3).brightness(2).colors(&COLORS).commit().unwrap();
+    let sent = mock.reports().len();
+
+    // The mock answers with the last report it was sent
+    let keyboard = Keyboard::with_transport(mock.clone(), Arc::new(AtomicBool::new(false))).unwrap();
+
+    assert_eq!(keyboard.current_state(), &LightingState::new(BaseEffects::Breath, 3, 2, COLORS).unwrap());
+    assert_eq!(keyboard.startup_state(), Some(keyboard.current_state()));
+    assert_eq!(mock.reports().len(), sent, "nothing should be written when the state could be read back");
+}
+
+/// Answers with a report cut short after the colors
+struct ShortTransport;
+
+impl Transport for ShortTransport {
+    fn send_feature_report(&mut self, _payload: &[u8; PAYLOAD_SIZE]) -> Result<()> {
+        Ok(())
+    }
+
+    fn get_feature_report(&mut self, buffer: &mut [u8; PAYLOAD_SIZE]) -> Result<usize> {
+        buffer[..5].copy_from_slice(&[0xcc, 0x16, 0x03, 0x02, 0x02]);
+
+        Ok(5 + 12)
+    }
+}
+
+#[test]
+fn short_reports_are_not_adopted() {
+    let mut keyboard = Keyboard::with_transport(ShortTransport, Arc::new(AtomicBool::new(false))).unwrap();
+
+    assert_eq!(keyboard.startup_state(), None);
+    assert!(matches!(keyboard.read_state(), Err(Error::InvalidPayload)));
+    assert_eq!(keyboard.current_state(), &LightingState::default());
+}
+
+#[test]
+fn payload_bytes_for_each_effect() {
+    let cases = [
+        (BaseEffects::Static, 0x01, [0, 0]),
+        (BaseEffects::Breath, 0x03, [0, 0]),
+        (BaseEffects::Smooth, 0x06, [0, 0]),
+        (BaseEffects::LeftWave, 0x04, [0, 1]),
+        (BaseEffects::RightWave, 0x04, [1, 0]),
+    ];
+
+    for (effect, effect_byte, wave_bytes) in cases {
+        let (mut keyboard, mock) = keyboard();
+        keyboard.transaction().effect(effect).speed(2).brightness(2).colors(&COLORS).commit().unwrap();
+
+        let payload = mock.last_report().unwrap();
+
+        assert_eq!(payload[..5], [0xcc, 0x16, effect_byte, 2, 2], "{effect:?}");
+        assert_eq!(payload[18..20], wave_bytes, "{effect:?}");
+
+        // Only the effects showing the zone colors carry them
+        let colors = if let BaseEffects::Static | BaseEffects::Breath = effect { COLORS.to_array() } else { [0; 12] };
+        assert_eq!(payload[5..17], colors, "{effect:?}");
+    }
+}
+
+#[test]
+fn repeated_writes_are_skipped() {
+    let (mut keyboard, mock) = keyboard();
+
+    keyboard.set_colors(&COLORS).unwrap();
+    keyboard.set_colors(&COLORS).unwrap();
+    keyboard.set_brightness(1).unwrap();
+
+    assert_eq!(mock.reports().len(), 2);
+}
+
+#[test]
+fn transition_frames_over_the_max_fps_are_dropped() {
+    let (mut keyboard, mock) = keyboard();
+    keyboard.set_max_fps(Some(10));
+    mock.clear();
+
+    // Every frame comes right after the write made when opening, only the final colors wait for their turn
+    keyboard.transition_colors(&COLORS, 10, 0).unwrap();
+
+    assert_eq!(mock.reports().len(), 1);
+    assert_eq!(mock.last_report().unwrap()[5..17], COLORS.to_array());
+}
+
+#[test]
+fn transactions_are_sent_in_a_single_write() {
+    let (mut keyboard, mock) = keyboard();
+    mock.clear();
+
+    keyboard.transaction().effect(BaseEffects::Breath).speed(4).brightness(2).colors(&COLORS).commit().unwrap();
+
+    assert_eq!(mock.reports().len(), 1);
+    assert_eq!(keyboard.current_state(), &LightingState::new(BaseEffects::Breath, 4, 2, COLORS).unwrap());
+}
+
+#[test]
+fn out_of_range_transactions_leave_the_keyboard_untouched() {
+    let (mut keyboard, mock) = keyboard();
+    keyboard.set_colors(&COLORS).unwrap();
+    mock.clear();
+
+    let before = keyboard.current_state().clone();
+    let result = keyboard.transaction().effect(BaseEffects::Breath).speed(5).commit();
+
+    assert!(matches!(result, Err(Error::RangeError(_))));
+    assert!(mock.reports().is_empty());
+    assert_eq!(keyboard.current_state(), &before);
+}
+
+#[test]
+fn dropped_transactions_send_nothing() {
+    let (mut keyboard, mock) = keyboard();
+    mock.clear();
+
+    let _ = keyboard.transaction().effect(BaseEffects::Smooth);
+
+    assert!(mock.reports().is_empty());
+    assert_eq!(keyboard.current_state(), &LightingState::default());
+}
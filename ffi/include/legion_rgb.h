@@ -0,0 +1,155 @@
+#ifndef LEGION_RGB_H
+#define LEGION_RGB_H
+
+/* Generated by cbindgen from ffi/src/lib.rs, do not edit by hand */
+
+#include <stdarg.h>
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+#include <stdlib.h>
+
+/**
+ * The size of the reports written by `legion_rgb_mock_last_report`
+ */
+#define LEGION_RGB_PAYLOAD_SIZE 33
+
+/**
+ * The effects run by the keyboard itself, as taken by `legion_rgb_set_effect`
+ */
+typedef enum LegionRgbEffect {
+  LEGION_RGB_EFFECT_STATIC = 0,
+  LEGION_RGB_EFFECT_BREATH,
+  LEGION_RGB_EFFECT_SMOOTH,
+  LEGION_RGB_EFFECT_LEFT_WAVE,
+  LEGION_RGB_EFFECT_RIGHT_WAVE,
+} LegionRgbEffect;
+
+/**
+ * Mirrors the variants of the driver's `Error`, plus the failures specific to the C API
+ */
+typedef enum LegionRgbError {
+  LEGION_RGB_ERROR_OK = 0,
+  LEGION_RGB_ERROR_HID_ERROR,
+  LEGION_RGB_ERROR_DEVICE_NOT_FOUND,
+  LEGION_RGB_ERROR_DISCONNECTED,
+  LEGION_RGB_ERROR_RANGE_ERROR,
+  LEGION_RGB_ERROR_IO_ERROR,
+  LEGION_RGB_ERROR_READBACK_UNSUPPORTED,
+  LEGION_RGB_ERROR_INVALID_PAYLOAD,
+  LEGION_RGB_ERROR_INVALID_CAPTURE,
+  LEGION_RGB_ERROR_DEVICE_DATABASE_ERROR,
+  LEGION_RGB_ERROR_PERMISSION_DENIED,
+  /**
+   * A pointer argument was null
+   */
+  LEGION_RGB_ERROR_NULL_POINTER,
+  /**
+   * The keyboard was not opened with `legion_rgb_keyboard_open_mock`
+   */
+  LEGION_RGB_ERROR_NOT_A_MOCK,
+  /**
+   * The driver panicked, the keyboard should not be used anymore
+   */
+  LEGION_RGB_ERROR_PANIC,
+} LegionRgbError;
+
+/**
+ * An open keyboard, only ever handled through a pointer on the C side
+ */
+typedef struct LegionRgbKeyboard LegionRgbKeyboard;
+
+/**
+ * Open the first supported keyboard found, `*out` is set to null on failure
+ *
+ * # Safety
+ *
+ * `out` must be null or valid for writes
+ */
+enum LegionRgbError legion_rgb_keyboard_open(struct LegionRgbKeyboard **out);
+
+/**
+ * Open a keyboard that only records what is sent to it, see `legion_rgb_mock_last_report`
+ *
+ * # Safety
+ *
+ * `out` must be null or valid for writes
+ */
+enum LegionRgbError legion_rgb_keyboard_open_mock(struct LegionRgbKeyboard **out);
+
+/**
+ * Close a keyboard, passing null does nothing
+ *
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+ */
+void legion_rgb_keyboard_free(struct LegionRgbKeyboard *keyboard);
+
+/**
+ * Set one of the `LegionRgbEffect` values, anything else is a `LEGION_RGB_ERROR_RANGE_ERROR`
+ *
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+ */
+enum LegionRgbError legion_rgb_set_effect(struct LegionRgbKeyboard *keyboard,
+                                          uint32_t effect);
+
+/**
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+ */
+enum LegionRgbError legion_rgb_set_speed(struct LegionRgbKeyboard *keyboard,
+                                         uint8_t speed);
+
+/**
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+ */
+enum LegionRgbError legion_rgb_set_brightness(struct LegionRgbKeyboard *keyboard,
+                                              uint8_t brightness);
+
+/**
+ * Only has an effect with the static and breath effects
+ *
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet,
+ * `colors` must be null or point to 12 readable bytes
+ */
+enum LegionRgbError legion_rgb_set_colors(struct LegionRgbKeyboard *keyboard,
+                                          const uint8_t *colors);
+
+/**
+ * Fade to the given colors in `steps` steps, waiting `delay_between_steps` ms between each, blocks until done
+ *
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet,
+ * `colors` must be null or point to 12 readable bytes
+ */
+enum LegionRgbError legion_rgb_transition_colors(struct LegionRgbKeyboard *keyboard,
+                                                 const uint8_t *colors,
+                                                 uint8_t steps,
+                                                 uint64_t delay_between_steps);
+
+/**
+ * Copy the last report a mock keyboard was sent into `out`
+ *
+ * # Safety
+ *
+ * `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet,
+ * `out` must be null or point to `LEGION_RGB_PAYLOAD_SIZE` writable bytes
+ */
+enum LegionRgbError legion_rgb_mock_last_report(const struct LegionRgbKeyboard *keyboard,
+                                                uint8_t *out);
+
+/**
+ * A static, human readable description of an error code
+ */
+const char *legion_rgb_error_message(enum LegionRgbError error);
+
+#endif /* LEGION_RGB_H */
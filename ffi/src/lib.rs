@@ -0,0 +1,399 @@
+//! C API around the driver, see `include/legion_rgb.h`
+//!
+//! Every function returns a [`LegionRgbError`], `LEGION_RGB_ERROR_OK` meaning success. Colors are passed as 12 bytes in the
+//! `[r, g, b, r, g, b...]` layout, one triplet per zone from left to right.
+
+use std::{
+    ffi::c_char,
+    panic::{self, AssertUnwindSafe},
+    ptr,
+    sync::{atomic::AtomicBool, Arc},
+};
+
+use legion_rgb_driver::{
+    error::{Error, Result},
+    transport::{MockTransport, PAYLOAD_SIZE},
+    BaseEffects, Keyboard,
+};
+
+/// The size of the reports written by `legion_rgb_mock_last_report`
+pub const LEGION_RGB_PAYLOAD_SIZE: usize = 33;
+
+const _: () = assert!(LEGION_RGB_PAYLOAD_SIZE == PAYLOAD_SIZE);
+
+/// Mirrors the variants of the driver's `Error`, plus the failures specific to the C API
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegionRgbError {
+    Ok = 0,
+    HidError,
+    DeviceNotFound,
+    Disconnected,
+    RangeError,
+    IoError,
+    ReadbackUnsupported,
+    InvalidPayload,
+    InvalidCapture,
+    DeviceDatabaseError,
+    PermissionDenied,
+    /// A pointer argument was null
+    NullPointer,
+    /// The keyboard was not opened with `legion_rgb_keyboard_open_mock`
+    NotAMock,
+    /// The driver panicked, the keyboard should not be used anymore
+    Panic,
+}
+
+impl From<&Error> for LegionRgbError {
+    fn from(err: &Error) -> Self {
+        match err {
+            Error::HidError { .. } => Self::HidError,
+            Error::DeviceNotFound => Self::DeviceNotFound,
+            Error::PermissionDenied { .. } => Self::PermissionDenied,
+            Error::Disconnected => Self::Disconnected,
+            Error::RangeError(_) => Self::RangeError,
+            Error::IoError(_) => Self::IoError,
+            Error::ReadbackUnsupported => Self::ReadbackUnsupported,
+            Error::InvalidPayload => Self::InvalidPayload,
+            Error::InvalidCapture { .. } => Self::InvalidCapture,
+            Error::DeviceDatabaseError(_) => Self::DeviceDatabaseError,
+        }
+    }
+}
+
+/// The effects run by the keyboard itself, as taken by `legion_rgb_set_effect`
+#[repr(C)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LegionRgbEffect {
+    Static = 0,
+    Breath,
+    Smooth,
+    LeftWave,
+    RightWave,
+}
+
+impl LegionRgbEffect {
+    // Taken as a plain integer from C since an out of range enum value would be undefined behavior on the Rust side
+    fn from_raw(raw: u32) -> Option<BaseEffects> {
+        let effect = match raw {
+            r if r == Self::Static as u32 => BaseEffects::Static,
+            r if r == Self::Breath as u32 => BaseEffects::Breath,
+            r if r == Self::Smooth as u32 => BaseEffects::Smooth,
+            r if r == Self::LeftWave as u32 => BaseEffects::LeftWave,
+            r if r == Self::RightWave as u32 => BaseEffects::RightWave,
+            _ => return None,
+        };
+
+        Some(effect)
+    }
+}
+
+/// An open keyboard, only ever handled through a pointer on the C side
+pub struct LegionRgbKeyboard {
+    keyboard: Keyboard,
+    mock: Option<MockTransport>,
+}
+
+fn catch(f: impl FnOnce() -> Result<()>) -> LegionRgbError {
+    match panic::catch_unwind(AssertUnwindSafe(f)) {
+        Ok(Ok(())) => LegionRgbError::Ok,
+        Ok(Err(err)) => LegionRgbError::from(&err),
+        Err(_) => LegionRgbError::Panic,
+    }
+}
+
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+unsafe fn with_keyboard(keyboard: *mut LegionRgbKeyboard, f: impl FnOnce(&mut Keyboard) -> Result<()>) -> LegionRgbError {
+    match keyboard.as_mut() {
+        Some(keyboard) => catch(|| f(&mut keyboard.keyboard)),
+        None => LegionRgbError::NullPointer,
+    }
+}
+
+/// # Safety
+///
+/// `out` must be null or valid for writes
+unsafe fn open(out: *mut *mut LegionRgbKeyboard, open_keyboard: impl FnOnce() -> Result<LegionRgbKeyboard>) -> LegionRgbError {
+    if out.is_null() {
+        return LegionRgbError::NullPointer;
+    }
+
+    *out = ptr::null_mut();
+
+    let mut opened = None;
+    let result = catch(|| {
+        opened = Some(open_keyboard()?);
+        Ok(())
+    });
+
+    if let Some(keyboard) = opened {
+        *out = Box::into_raw(Box::new(keyboard));
+    }
+
+    result
+}
+
+/// Open the first supported keyboard found, `*out` is set to null on failure
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_keyboard_open(out: *mut *mut LegionRgbKeyboard) -> LegionRgbError {
+    open(out, || {
+        Ok(LegionRgbKeyboard {
+            keyboard: legion_rgb_driver::get_keyboard(Arc::new(AtomicBool::new(false)))?,
+            mock: None,
+        })
+    })
+}
+
+/// Open a keyboard that only records what is sent to it, see `legion_rgb_mock_last_report`
+///
+/// # Safety
+///
+/// `out` must be null or valid for writes
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_keyboard_open_mock(out: *mut *mut LegionRgbKeyboard) -> LegionRgbError {
+    open(out, || {
+        let mock = MockTransport::new();
+
+        Ok(LegionRgbKeyboard {
+            keyboard: Keyboard::with_transport(mock.clone(), Arc::new(AtomicBool::new(false)))?,
+            mock: Some(mock),
+        })
+    })
+}
+
+/// Close a keyboard, passing null does nothing
+///
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_keyboard_free(keyboard: *mut LegionRgbKeyboard) {
+    if !keyboard.is_null() {
+        drop(Box::from_raw(keyboard));
+    }
+}
+
+/// Set one of the `LegionRgbEffect` values, anything else is a `LEGION_RGB_ERROR_RANGE_ERROR`
+///
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_set_effect(keyboard: *mut LegionRgbKeyboard, effect: u32) -> LegionRgbError {
+    let Some(effect) = LegionRgbEffect::from_raw(effect) else {
+        return LegionRgbError::RangeError;
+    };
+
+    with_keyboard(keyboard, |keyboard| keyboard.set_effect(effect))
+}
+
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_set_speed(keyboard: *mut LegionRgbKeyboard, speed: u8) -> LegionRgbError {
+    with_keyboard(keyboard, |keyboard| keyboard.set_speed(speed))
+}
+
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_set_brightness(keyboard: *mut LegionRgbKeyboard, brightness: u8) -> LegionRgbError {
+    with_keyboard(keyboard, |keyboard| keyboard.set_brightness(brightness))
+}
+
+/// Only has an effect with the static and breath effects
+///
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet,
+/// `colors` must be null or point to 12 readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_set_colors(keyboard: *mut LegionRgbKeyboard, colors: *const u8) -> LegionRgbError {
+    let Some(colors) = colors.cast::<[u8; 12]>().as_ref() else {
+        return LegionRgbError::NullPointer;
+    };
+
+    with_keyboard(keyboard, |keyboard| keyboard.set_colors_to(colors))
+}
+
+/// Fade to the given colors in `steps` steps, waiting `delay_between_steps` ms between each, blocks until done
+///
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet,
+/// `colors` must be null or point to 12 readable bytes
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_transition_colors(keyboard: *mut LegionRgbKeyboard, colors: *const u8, steps: u8, delay_between_steps: u64) -> LegionRgbError {
+    let Some(colors) = colors.cast::<[u8; 12]>().as_ref() else {
+        return LegionRgbError::NullPointer;
+    };
+
+    with_keyboard(keyboard, |keyboard| keyboard.transition_colors_to(colors, steps, delay_between_steps))
+}
+
+/// Copy the last report a mock keyboard was sent into `out`
+///
+/// # Safety
+///
+/// `keyboard` must be null or a pointer returned by one of the `legion_rgb_keyboard_open` functions that was not freed yet,
+/// `out` must be null or point to `LEGION_RGB_PAYLOAD_SIZE` writable bytes
+#[no_mangle]
+pub unsafe extern "C" fn legion_rgb_mock_last_report(keyboard: *const LegionRgbKeyboard, out: *mut u8) -> LegionRgbError {
+    let (Some(keyboard), Some(out)) = (keyboard.as_ref(), out.cast::<[u8; PAYLOAD_SIZE]>().as_mut()) else {
+        return LegionRgbError::NullPointer;
+    };
+
+    let Some(mock) = &keyboard.mock else {
+        return LegionRgbError::NotAMock;
+    };
+
+    match mock.last_report() {
+        Some(report) => {
+            *out = report;
+            LegionRgbError::Ok
+        }
+        None => LegionRgbError::InvalidPayload,
+    }
+}
+
+/// A static, human readable description of an error code
+#[no_mangle]
+pub extern "C" fn legion_rgb_error_message(error: LegionRgbError) -> *const c_char {
+    // Nul terminated so that they can be handed out as C strings
+    let message: &'static [u8] = match error {
+        LegionRgbError::Ok => b"No error\0",
+        LegionRgbError::HidError => b"The keyboard could not be written to\0",
+        LegionRgbError::DeviceNotFound => b"Couldn't find device\0",
+        LegionRgbError::PermissionDenied => b"Not allowed to open the keyboard, on Linux a udev rule is needed\0",
+        LegionRgbError::Disconnected => b"The device was disconnected\0",
+        LegionRgbError::RangeError => b"A value specified was not within the expected range\0",
+        LegionRgbError::IoError => b"An I/O error occurred\0",
+        LegionRgbError::ReadbackUnsupported => b"The device did not report its current state\0",
+        LegionRgbError::InvalidPayload => b"Received a malformed payload\0",
+        LegionRgbError::InvalidCapture => b"The capture file is not valid\0",
+        LegionRgbError::DeviceDatabaseError => b"The device database is not valid\0",
+        LegionRgbError::NullPointer => b"A required pointer was null\0",
+        LegionRgbError::NotAMock => b"The keyboard is not a mock\0",
+        LegionRgbError::Panic => b"The driver panicked\0",
+    };
+
+    message.as_ptr().cast()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::*;
+
+    const COLORS: [u8; 12] = [255, 0, 0, 0, 255, 0, 0, 0, 255, 10, 20, 30];
+
+    /// Open a mock keyboard, run `f` on it and free it
+    fn with_mock(f: impl FnOnce(*mut LegionRgbKeyboard)) {
+        let mut keyboard = ptr::null_mut();
+
+        unsafe {
+            assert_eq!(legion_rgb_keyboard_open_mock(&mut keyboard), LegionRgbError::Ok);
+            assert!(!keyboard.is_null());
+
+            f(keyboard);
+
+            legion_rgb_keyboard_free(keyboard);
+        }
+    }
+
+    fn last_report(keyboard: *mut LegionRgbKeyboard) -> [u8; LEGION_RGB_PAYLOAD_SIZE] {
+        let mut report = [0; LEGION_RGB_PAYLOAD_SIZE];
+
+        assert_eq!(unsafe { legion_rgb_mock_last_report(keyboard, report.as_mut_ptr()) }, LegionRgbError::Ok);
+
+        report
+    }
+
+    #[test]
+    fn colors_are_read_back_from_the_mock() {
+        with_mock(|keyboard| unsafe {
+            assert_eq!(legion_rgb_set_effect(keyboard, LegionRgbEffect::Breath as u32), LegionRgbError::Ok);
+            assert_eq!(legion_rgb_set_speed(keyboard, 3), LegionRgbError::Ok);
+            assert_eq!(legion_rgb_set_brightness(keyboard, 2), LegionRgbError::Ok);
+            assert_eq!(legion_rgb_set_colors(keyboard, COLORS.as_ptr()), LegionRgbError::Ok);
+
+            let report = last_report(keyboard);
+
+            assert_eq!(report[..5], [0xcc, 0x16, 0x03, 3, 2]);
+            assert_eq!(report[5..17], COLORS);
+        });
+    }
+
+    #[test]
+    fn transitions_end_on_the_target_colors() {
+        with_mock(|keyboard| unsafe {
+            assert_eq!(legion_rgb_transition_colors(keyboard, COLORS.as_ptr(), 5, 0), LegionRgbError::Ok);
+
+            assert_eq!(last_report(keyboard)[5..17], COLORS);
+        });
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        with_mock(|keyboard| unsafe {
+            let before = last_report(keyboard);
+
+            assert_eq!(legion_rgb_set_effect(keyboard, 5), LegionRgbError::RangeError);
+            assert_eq!(legion_rgb_set_speed(keyboard, 0), LegionRgbError::RangeError);
+            assert_eq!(legion_rgb_set_brightness(keyboard, 3), LegionRgbError::RangeError);
+
+            assert_eq!(last_report(keyboard), before);
+        });
+    }
+
+    #[test]
+    fn null_pointers_are_rejected() {
+        unsafe {
+            assert_eq!(legion_rgb_keyboard_open_mock(ptr::null_mut()), LegionRgbError::NullPointer);
+            assert_eq!(legion_rgb_set_speed(ptr::null_mut(), 1), LegionRgbError::NullPointer);
+
+            with_mock(|keyboard| {
+                assert_eq!(legion_rgb_set_colors(keyboard, ptr::null()), LegionRgbError::NullPointer);
+                assert_eq!(legion_rgb_mock_last_report(keyboard, ptr::null_mut()), LegionRgbError::NullPointer);
+            });
+
+            // Freeing null does nothing
+            legion_rgb_keyboard_free(ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn every_error_has_a_message() {
+        let errors = [
+            LegionRgbError::Ok,
+            LegionRgbError::HidError,
+            LegionRgbError::DeviceNotFound,
+            LegionRgbError::Disconnected,
+            LegionRgbError::RangeError,
+            LegionRgbError::IoError,
+            LegionRgbError::ReadbackUnsupported,
+            LegionRgbError::InvalidPayload,
+            LegionRgbError::InvalidCapture,
+            LegionRgbError::DeviceDatabaseError,
+            LegionRgbError::PermissionDenied,
+            LegionRgbError::NullPointer,
+            LegionRgbError::NotAMock,
+            LegionRgbError::Panic,
+        ];
+
+        for error in errors {
+            let message = unsafe { CStr::from_ptr(legion_rgb_error_message(error)) };
+
+            assert!(!message.to_bytes().is_empty());
+        }
+    }
+}
@@ -0,0 +1,308 @@
+use core::{
+    fmt,
+    ops::{Index, IndexMut},
+    str::FromStr,
+};
+
+use crate::{
+    error::{ParseColorError, RangeError, RangeErrorKind},
+    ZONE_RANGE,
+};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct Rgb {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+}
+
+impl Rgb {
+    pub const BLACK: Self = Self::new(0, 0, 0);
+    pub const WHITE: Self = Self::new(255, 255, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8) -> Self {
+        Self { r, g, b }
+    }
+
+    /// Parse a color in the `#rrggbb` or `#rgb` formats, the `#` being optional
+    pub fn from_hex(hex: &str) -> Result<Self, ParseColorError> {
+        let digits = hex.strip_prefix('#').unwrap_or(hex);
+
+        if !digits.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(ParseColorError);
+        }
+
+        let channel = |range: core::ops::Range<usize>| u8::from_str_radix(&digits[range], 16).map_err(|_| ParseColorError);
+
+        match digits.len() {
+            6 => Ok(Self::new(channel(0..2)?, channel(2..4)?, channel(4..6)?)),
+            // Each digit is repeated, "f80" is the same as "ff8800"
+            3 => Ok(Self::new(channel(0..1)? * 17, channel(1..2)? * 17, channel(2..3)? * 17)),
+            _ => Err(ParseColorError),
+        }
+    }
+
+    #[cfg(feature = "std")]
+    pub fn to_hex(self) -> String {
+        self.to_string()
+    }
+
+    /// Build a color from a hue in degrees and a saturation and value between 0 and 1
+    #[cfg(feature = "std")]
+    pub fn from_hsv(hue: f32, saturation: f32, value: f32) -> Self {
+        let hue = hue.rem_euclid(360.0);
+        let saturation = saturation.clamp(0.0, 1.0);
+        let value = value.clamp(0.0, 1.0);
+
+        let chroma = value * saturation;
+        let x = chroma * (1.0 - ((hue / 60.0) % 2.0 - 1.0).abs());
+        let m = value - chroma;
+
+        let (r, g, b) = match hue {
+            h if h < 60.0 => (chroma, x, 0.0),
+            h if h < 120.0 => (x, chroma, 0.0),
+            h if h < 180.0 => (0.0, chroma, x),
+            h if h < 240.0 => (0.0, x, chroma),
+            h if h < 300.0 => (x, 0.0, chroma),
+            _ => (chroma, 0.0, x),
+        };
+
+        let to_channel = |c: f32| ((c + m) * 255.0).round() as u8;
+
+        Self::new(to_channel(r), to_channel(g), to_channel(b))
+    }
+
+    /// The hue in degrees and the saturation and value between 0 and 1
+    #[cfg(feature = "std")]
+    pub fn to_hsv(self) -> (f32, f32, f32) {
+        let [r, g, b] = [self.r, self.g, self.b].map(|c| f32::from(c) / 255.0);
+
+        let max = r.max(g).max(b);
+        let min = r.min(g).min(b);
+        let delta = max - min;
+
+        let hue = if delta == 0.0 {
+            0.0
+        } else if max == r {
+            60.0 * ((g - b) / delta).rem_euclid(6.0)
+        } else if max == g {
+            60.0 * ((b - r) / delta + 2.0)
+        } else {
+            60.0 * ((r - g) / delta + 4.0)
+        };
+
+        let saturation = if max == 0.0 { 0.0 } else { delta / max };
+
+        (hue, saturation, max)
+    }
+
+    /// Scale every channel down to `percent` (capped at 100) of its value
+    pub fn dimmed(self, percent: u8) -> Self {
+        let percent = u16::from(percent.min(100));
+        let scale = |c: u8| ((u16::from(c) * percent + 50) / 100) as u8;
+
+        Self::new(scale(self.r), scale(self.g), scale(self.b))
+    }
+}
+
+impl From<[u8; 3]> for Rgb {
+    fn from([r, g, b]: [u8; 3]) -> Self {
+        Self::new(r, g, b)
+    }
+}
+
+impl From<Rgb> for [u8; 3] {
+    fn from(color: Rgb) -> Self {
+        [color.r, color.g, color.b]
+    }
+}
+
+impl FromStr for Rgb {
+    type Err = ParseColorError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::from_hex(s)
+    }
+}
+
+impl fmt::Display for Rgb {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "#{:02x}{:02x}{:02x}", self.r, self.g, self.b)
+    }
+}
+
+/// One of the four lighting zones, from left to right
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum ZoneId {
+    Left,
+    CenterLeft,
+    CenterRight,
+    Right,
+}
+
+impl ZoneId {
+    pub const ALL: [Self; 4] = [Self::Left, Self::CenterLeft, Self::CenterRight, Self::Right];
+
+    pub const fn index(self) -> usize {
+        self as usize
+    }
+}
+
+impl TryFrom<u8> for ZoneId {
+    type Error = RangeError;
+
+    fn try_from(index: u8) -> Result<Self, Self::Error> {
+        if !ZONE_RANGE.contains(&index) {
+            return Err(RangeError { kind: RangeErrorKind::Zone });
+        }
+
+        Ok(Self::ALL[usize::from(index)])
+    }
+}
+
+/// The colors of every zone of the keyboard
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct ZoneColors(pub [Rgb; 4]);
+
+impl ZoneColors {
+    pub const fn solid(color: Rgb) -> Self {
+        Self([color; 4])
+    }
+
+    pub fn from_array(arr: [u8; 12]) -> Self {
+        Self(ZoneId::ALL.map(|zone| {
+            let start = zone.index() * 3;
+            Rgb::new(arr[start], arr[start + 1], arr[start + 2])
+        }))
+    }
+
+    /// Flatten the colors into the `[r, g, b, r, g, b...]` layout used by the keyboard
+    pub fn to_array(self) -> [u8; 12] {
+        let mut arr = [0; 12];
+
+        for (color, chunk) in self.0.iter().zip(arr.chunks_exact_mut(3)) {
+            chunk.copy_from_slice(&<[u8; 3]>::from(*color));
+        }
+
+        arr
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (ZoneId, Rgb)> + '_ {
+        ZoneId::ALL.into_iter().zip(self.0.iter().copied())
+    }
+
+    pub fn dimmed(self, percent: u8) -> Self {
+        Self(self.0.map(|color| color.dimmed(percent)))
+    }
+
+    /// Move every color one zone to the left, wrapping around
+    pub fn rotate_left(&mut self) {
+        self.0.rotate_left(1);
+    }
+
+    /// Move every color one zone to the right, wrapping around
+    pub fn rotate_right(&mut self) {
+        self.0.rotate_right(1);
+    }
+}
+
+impl From<[Rgb; 4]> for ZoneColors {
+    fn from(colors: [Rgb; 4]) -> Self {
+        Self(colors)
+    }
+}
+
+impl From<[u8; 12]> for ZoneColors {
+    fn from(arr: [u8; 12]) -> Self {
+        Self::from_array(arr)
+    }
+}
+
+impl From<ZoneColors> for [u8; 12] {
+    fn from(colors: ZoneColors) -> Self {
+        colors.to_array()
+    }
+}
+
+impl Index<ZoneId> for ZoneColors {
+    type Output = Rgb;
+
+    fn index(&self, zone: ZoneId) -> &Self::Output {
+        &self.0[zone.index()]
+    }
+}
+
+impl IndexMut<ZoneId> for ZoneColors {
+    fn index_mut(&mut self, zone: ZoneId) -> &mut Self::Output {
+        &mut self.0[zone.index()]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_colors_are_parsed() {
+        assert_eq!(Rgb::from_hex("#ff8000"), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!(Rgb::from_hex("FF8000"), Ok(Rgb::new(255, 128, 0)));
+        assert_eq!(Rgb::from_hex("#f80"), Ok(Rgb::new(255, 136, 0)));
+        assert_eq!(Rgb::from_hex("0a0"), Ok(Rgb::new(0, 170, 0)));
+        assert_eq!("#123456".parse(), Ok(Rgb::new(0x12, 0x34, 0x56)));
+    }
+
+    #[test]
+    fn bad_hex_colors_are_rejected() {
+        for hex in ["", "#", "#ff80", "#ff80000", "#gg8000", "#+f8000", "##ff8000", "#ff 800", "#ff800é"] {
+            assert_eq!(Rgb::from_hex(hex), Err(ParseColorError), "{hex}");
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hex_colors_round_trip() {
+        let color = Rgb::new(1, 171, 255);
+
+        assert_eq!(color.to_hex(), "#01abff");
+        assert_eq!(Rgb::from_hex(&color.to_hex()), Ok(color));
+    }
+
+    #[test]
+    fn zones_are_looked_up_by_index() {
+        assert_eq!(ZoneId::try_from(0), Ok(ZoneId::Left));
+        assert_eq!(ZoneId::try_from(3), Ok(ZoneId::Right));
+        assert_eq!(ZoneId::try_from(4), Err(RangeError { kind: RangeErrorKind::Zone }));
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hsv_round_trips() {
+        for r in (0..=255).step_by(15) {
+            for g in (0..=255).step_by(15) {
+                for b in (0..=255).step_by(15) {
+                    let color = Rgb::new(r, g, b);
+                    let (hue, saturation, value) = color.to_hsv();
+
+                    assert_eq!(Rgb::from_hsv(hue, saturation, value), color);
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "std")]
+    #[test]
+    fn hsv_of_known_colors() {
+        assert_eq!(Rgb::new(255, 0, 0).to_hsv(), (0.0, 1.0, 1.0));
+        assert_eq!(Rgb::new(0, 255, 0).to_hsv(), (120.0, 1.0, 1.0));
+        assert_eq!(Rgb::new(0, 0, 255).to_hsv(), (240.0, 1.0, 1.0));
+        assert_eq!(Rgb::WHITE.to_hsv(), (0.0, 0.0, 1.0));
+        assert_eq!(Rgb::BLACK.to_hsv(), (0.0, 0.0, 0.0));
+
+        // Hues wrap around and out of range saturations and values are clamped
+        assert_eq!(Rgb::from_hsv(360.0 + 60.0, 1.0, 1.0), Rgb::new(255, 255, 0));
+        assert_eq!(Rgb::from_hsv(-60.0, 2.0, 2.0), Rgb::new(255, 0, 255));
+    }
+}
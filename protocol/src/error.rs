@@ -0,0 +1,67 @@
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RangeError {
+    pub kind: RangeErrorKind,
+}
+
+impl fmt::Display for RangeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("RangeError: A value specified was not within the expected range")
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RangeErrorKind {
+    Zone,
+    Speed,
+    Brightness,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParseColorError;
+
+impl fmt::Display for ParseColorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ParseColorError: The color is not in the #rrggbb or #rgb format")
+    }
+}
+
+/// Why a payload could not be turned back into a lighting state
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DecodeError {
+    /// The header or effect bytes don't match anything the keyboard understands
+    InvalidPayload,
+    RangeError(RangeError),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InvalidPayload => f.write_str("Error: Received a malformed payload"),
+            Self::RangeError(err) => write!(f, "Error: {err}"),
+        }
+    }
+}
+
+impl From<RangeError> for DecodeError {
+    fn from(err: RangeError) -> Self {
+        Self::RangeError(err)
+    }
+}
+
+#[cfg(feature = "std")]
+impl std::error::Error for RangeError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for ParseColorError {}
+
+#[cfg(feature = "std")]
+impl std::error::Error for DecodeError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::InvalidPayload => None,
+            Self::RangeError(err) => Some(err),
+        }
+    }
+}
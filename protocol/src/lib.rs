@@ -0,0 +1,269 @@
+//! Encoding and decoding of the payloads understood by the 4-zone Legion keyboards, without any I/O
+//!
+//! Builds without the standard library when the default `std` feature is disabled, the `serde` feature derives
+//! `Serialize` and `Deserialize` for the public types.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+use core::fmt;
+
+use color::ZoneColors;
+use error::{DecodeError, RangeError, RangeErrorKind};
+
+pub mod color;
+pub mod error;
+
+/// The size of every report sent to the keyboard, including the report id
+pub const PAYLOAD_SIZE: usize = 33;
+
+pub const SPEED_RANGE: core::ops::RangeInclusive<u8> = 1..=4;
+pub const BRIGHTNESS_RANGE: core::ops::RangeInclusive<u8> = 1..=2;
+pub const ZONE_RANGE: core::ops::RangeInclusive<u8> = 0..=3;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BaseEffects {
+    Static,
+    Breath,
+    Smooth,
+    LeftWave,
+    RightWave,
+}
+
+/// Everything a single payload tells the keyboard
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LightingState {
+    effect_type: BaseEffects,
+    speed: u8,
+    brightness: u8,
+    colors: ZoneColors,
+}
+
+impl Default for LightingState {
+    fn default() -> Self {
+        Self {
+            effect_type: BaseEffects::Static,
+            speed: 1,
+            brightness: 1,
+            colors: ZoneColors::default(),
+        }
+    }
+}
+
+impl LightingState {
+    pub fn new(effect_type: BaseEffects, speed: u8, brightness: u8, colors: ZoneColors) -> Result<Self, RangeError> {
+        let state = Self {
+            effect_type,
+            speed,
+            brightness,
+            colors,
+        };
+
+        state.validate()?;
+
+        Ok(state)
+    }
+
+    pub fn effect_type(&self) -> BaseEffects {
+        self.effect_type
+    }
+
+    pub fn speed(&self) -> u8 {
+        self.speed
+    }
+
+    pub fn brightness(&self) -> u8 {
+        self.brightness
+    }
+
+    pub fn colors(&self) -> ZoneColors {
+        self.colors
+    }
+
+    /// The colors in the raw `[r, g, b, r, g, b...]` layout
+    pub fn rgb_values(&self) -> [u8; 12] {
+        self.colors.to_array()
+    }
+
+    // The setters don't check their values so that several changes can be staged, out of range ones are caught when encoding
+
+    pub fn set_effect_type(&mut self, effect_type: BaseEffects) {
+        self.effect_type = effect_type;
+    }
+
+    pub fn set_speed(&mut self, speed: u8) {
+        self.speed = speed;
+    }
+
+    pub fn set_brightness(&mut self, brightness: u8) {
+        self.brightness = brightness;
+    }
+
+    pub fn set_colors(&mut self, colors: ZoneColors) {
+        self.colors = colors;
+    }
+
+    pub fn colors_mut(&mut self) -> &mut ZoneColors {
+        &mut self.colors
+    }
+
+    pub fn validate(&self) -> Result<(), RangeError> {
+        if !SPEED_RANGE.contains(&self.speed) {
+            return Err(RangeError { kind: RangeErrorKind::Speed });
+        }
+        if !BRIGHTNESS_RANGE.contains(&self.brightness) {
+            return Err(RangeError { kind: RangeErrorKind::Brightness });
+        }
+
+        Ok(())
+    }
+
+    /// Encode the state into a payload for the keyboard
+    pub fn to_payload(&self) -> Result<[u8; PAYLOAD_SIZE], RangeError> {
+        self.validate()?;
+
+        let mut payload: [u8; PAYLOAD_SIZE] = [0; PAYLOAD_SIZE];
+        payload[0] = 0xcc;
+        payload[1] = 0x16;
+        payload[2] = match self.effect_type {
+            BaseEffects::Static => 0x01,
+            BaseEffects::Breath => 0x03,
+            BaseEffects::Smooth => 0x06,
+            BaseEffects::LeftWave => {
+                payload[19] = 0x1;
+                0x04
+            }
+            BaseEffects::RightWave => {
+                payload[18] = 0x1;
+                0x04
+            }
+        };
+
+        payload[3] = self.speed;
+        payload[4] = self.brightness;
+
+        if let BaseEffects::Static | BaseEffects::Breath = self.effect_type {
+            payload[5..(12 + 5)].copy_from_slice(&self.colors.to_array());
+        };
+
+        Ok(payload)
+    }
+
+    /// Decode a payload as sent to the keyboard
+    pub fn from_payload(payload: &[u8; PAYLOAD_SIZE]) -> Result<Self, DecodeError> {
+        if payload[0] != 0xcc || payload[1] != 0x16 {
+            return Err(DecodeError::InvalidPayload);
+        }
+
+        let effect_type = match (payload[2], payload[18], payload[19]) {
+            (0x01, ..) => BaseEffects::Static,
+            (0x03, ..) => BaseEffects::Breath,
+            (0x06, ..) => BaseEffects::Smooth,
+            (0x04, _, 0x1) => BaseEffects::LeftWave,
+            (0x04, 0x1, _) => BaseEffects::RightWave,
+            _ => return Err(DecodeError::InvalidPayload),
+        };
+
+        let mut rgb_values = [0; 12];
+        rgb_values.copy_from_slice(&payload[5..(12 + 5)]);
+
+        Ok(Self::new(effect_type, payload[3], payload[4], ZoneColors::from_array(rgb_values))?)
+    }
+}
+
+impl fmt::Display for LightingState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}, speed {}, brightness {}", self.effect_type, self.speed, self.brightness)?;
+
+        if let BaseEffects::Static | BaseEffects::Breath = self.effect_type {
+            f.write_str(", colors")?;
+
+            for color in self.colors.0 {
+                write!(f, " {color}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use color::Rgb;
+
+    const COLORS: ZoneColors = ZoneColors([Rgb::new(255, 0, 0), Rgb::new(0, 255, 0), Rgb::new(0, 0, 255), Rgb::new(10, 20, 30)]);
+
+    const EFFECTS: [BaseEffects; 5] = [BaseEffects::Static, BaseEffects::Breath, BaseEffects::Smooth, BaseEffects::LeftWave, BaseEffects::RightWave];
+
+    #[test]
+    fn payloads_round_trip() {
+        for effect in EFFECTS {
+            // The colors are only sent along with the effects showing them
+            let colors = if let BaseEffects::Static | BaseEffects::Breath = effect { COLORS } else { ZoneColors::default() };
+
+            for speed in SPEED_RANGE {
+                for brightness in BRIGHTNESS_RANGE {
+                    let state = LightingState::new(effect, speed, brightness, colors).unwrap();
+
+                    assert_eq!(LightingState::from_payload(&state.to_payload().unwrap()), Ok(state));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn colors_are_left_out_of_the_rainbow_effects() {
+        let payload = LightingState::new(BaseEffects::Smooth, 1, 1, COLORS).unwrap().to_payload().unwrap();
+
+        assert_eq!(payload[5..17], [0; 12]);
+    }
+
+    #[test]
+    fn bad_headers_are_rejected() {
+        let payload = LightingState::default().to_payload().unwrap();
+
+        for (index, byte) in [(0, 0xcd), (1, 0x17), (2, 0x02), (2, 0x00)] {
+            let mut payload = payload;
+            payload[index] = byte;
+
+            assert_eq!(LightingState::from_payload(&payload), Err(DecodeError::InvalidPayload), "byte {index} set to {byte:#04x}");
+        }
+
+        // A wave needs a direction
+        let mut payload = payload;
+        payload[2] = 0x04;
+        assert_eq!(LightingState::from_payload(&payload), Err(DecodeError::InvalidPayload));
+    }
+
+    #[test]
+    fn out_of_range_values_are_rejected() {
+        let speed = RangeError { kind: RangeErrorKind::Speed };
+        let brightness = RangeError { kind: RangeErrorKind::Brightness };
+
+        for (value, expected) in [(0, speed), (5, speed)] {
+            assert_eq!(LightingState::new(BaseEffects::Static, value, 1, COLORS), Err(expected));
+
+            let mut state = LightingState::default();
+            state.set_speed(value);
+            assert_eq!(state.to_payload(), Err(expected));
+
+            let mut payload = LightingState::default().to_payload().unwrap();
+            payload[3] = value;
+            assert_eq!(LightingState::from_payload(&payload), Err(DecodeError::RangeError(expected)));
+        }
+
+        for (value, expected) in [(0, brightness), (3, brightness)] {
+            assert_eq!(LightingState::new(BaseEffects::Static, 1, value, COLORS), Err(expected));
+
+            let mut state = LightingState::default();
+            state.set_brightness(value);
+            assert_eq!(state.to_payload(), Err(expected));
+
+            let mut payload = LightingState::default().to_payload().unwrap();
+            payload[4] = value;
+            assert_eq!(LightingState::from_payload(&payload), Err(DecodeError::RangeError(expected)));
+        }
+    }
+}